@@ -3,7 +3,46 @@
 
 use bencher::Bencher;
 use std::path::PathBuf;
-use tx_engine::{bank::Bank, transactions};
+use tx_engine::{
+    amount::Amount,
+    bank::{sharded::ShardedBank, Bank},
+    transactions::{self, Kind, Transaction},
+};
+
+const LARGE_DATASET_SIZE: u32 = 200_000;
+const LARGE_DATASET_CLIENTS: u32 = 10_000;
+const SHARDS: usize = 4;
+
+/// Deterministic mix of deposits, withdrawals and disputes over many clients, every client only
+/// disputes its own transactions
+fn generate_transactions(size: u32) -> Vec<Transaction> {
+    let mut seed: u64 = 42;
+    let mut next = move || {
+        // linear congruential generator, good enough to spread the load
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 33) as u32
+    };
+    let mut last_txs = vec![None; LARGE_DATASET_CLIENTS as usize];
+    (0..size)
+        .map(|tx| {
+            let client = (next() % LARGE_DATASET_CLIENTS) as u16;
+            let amount = Some(Amount::from_units(i64::from(next() % 1_000_000)));
+            match (next() % 10, last_txs[usize::from(client)]) {
+                (9, Some(last_tx)) => Transaction::new(Kind::Dispute, client, last_tx, None),
+                (6..=9, _) => {
+                    last_txs[usize::from(client)] = Some(tx);
+                    Transaction::new(Kind::Withdrawal, client, tx, amount)
+                }
+                _ => {
+                    last_txs[usize::from(client)] = Some(tx);
+                    Transaction::new(Kind::Deposit, client, tx, amount)
+                }
+            }
+        })
+        .collect()
+}
 
 fn bench_handle_transactions(bench: &mut Bencher) {
     bench.iter(|| {
@@ -18,5 +57,42 @@ fn bench_handle_transactions(bench: &mut Bencher) {
     })
 }
 
-benchmark_group!(benches, bench_handle_transactions);
+fn bench_apply_streamed_transactions(bench: &mut Bencher) {
+    bench.iter(|| {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("multiple_client_transactions.csv");
+
+        let mut bank = Bank::new();
+        let transactions = transactions::TransactionReader::from_path(&csv_file)
+            .expect("Parsing transactions failed");
+        for tx in transactions {
+            let _ = bank.apply(&tx.expect("Parsing transaction failed"));
+        }
+    })
+}
+
+fn bench_handle_large_dataset(bench: &mut Bencher) {
+    let transactions = generate_transactions(LARGE_DATASET_SIZE);
+    bench.iter(|| {
+        let mut bank = Bank::new();
+        bank.handle_transactions(transactions.iter().cloned());
+    })
+}
+
+fn bench_handle_large_dataset_sharded(bench: &mut Bencher) {
+    let transactions = generate_transactions(LARGE_DATASET_SIZE);
+    bench.iter(|| {
+        let mut bank = ShardedBank::new(SHARDS);
+        bank.handle_transactions(transactions.iter().cloned());
+    })
+}
+
+benchmark_group!(
+    benches,
+    bench_handle_transactions,
+    bench_apply_streamed_transactions,
+    bench_handle_large_dataset,
+    bench_handle_large_dataset_sharded
+);
 benchmark_main!(benches);
@@ -0,0 +1,251 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{error::Error, fmt, str::FromStr};
+
+/// Number of digits kept past the decimal point
+pub const DECIMALS: u32 = 4;
+
+const SCALE: i64 = 10_i64.pow(DECIMALS);
+
+/// An exact monetary value with a precision of four places past the decimal
+///
+/// The value is stored as a whole number of ten-thousandths, so adding and subtracting amounts
+/// never accumulates rounding errors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i64);
+
+#[derive(Debug, PartialEq)]
+pub enum ParseAmountError {
+    Empty,
+    InvalidDigit,
+    Overflow,
+}
+
+impl Error for ParseAmountError {}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseAmountError::Empty => fmt.write_str("cannot parse amount from empty string"),
+            ParseAmountError::InvalidDigit => fmt.write_str("invalid digit found in amount"),
+            ParseAmountError::Overflow => fmt.write_str("amount is too large"),
+        }
+    }
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(i64::MAX);
+    pub const MIN: Amount = Amount(i64::MIN);
+
+    /// Create an amount from a whole number of ten-thousandths
+    ///
+    /// # Examples
+    /// ```
+    /// use tx_engine::amount::Amount;
+    /// assert_eq!(Amount::from_units(15_000).to_string(), "1.5000");
+    /// ```
+    pub const fn from_units(units: i64) -> Amount {
+        Amount(units)
+    }
+
+    /// The amount as a whole number of ten-thousandths
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
+    pub fn checked_neg(self) -> Option<Amount> {
+        self.0.checked_neg().map(Amount)
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    /// Parse a decimal string exactly, rounding digits beyond the fourth decimal place half away
+    /// from zero
+    ///
+    /// # Examples
+    /// ```
+    /// use tx_engine::amount::Amount;
+    /// let amount: Amount = "2.00005".parse().unwrap();
+    /// assert_eq!(amount, Amount::from_units(20_001));
+    /// ```
+    fn from_str(s: &str) -> Result<Amount, ParseAmountError> {
+        let s = s.trim();
+        let (negative, digits) = match s.as_bytes().first() {
+            None => return Err(ParseAmountError::Empty),
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            Some(_) => (false, s),
+        };
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(ParseAmountError::Empty);
+        }
+        if !int_part
+            .bytes()
+            .chain(frac_part.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return Err(ParseAmountError::InvalidDigit);
+        }
+
+        let mut units: i64 = 0;
+        for digit in int_part.bytes() {
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add(i64::from(digit - b'0')))
+                .ok_or(ParseAmountError::Overflow)?;
+        }
+        let mut frac_digits = frac_part.bytes();
+        for _ in 0..DECIMALS {
+            let digit = frac_digits
+                .next()
+                .map_or(0, |digit| i64::from(digit - b'0'));
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add(digit))
+                .ok_or(ParseAmountError::Overflow)?;
+        }
+        if matches!(frac_digits.next(), Some(b'5'..=b'9')) {
+            units = units.checked_add(1).ok_or(ParseAmountError::Overflow)?;
+        }
+
+        Ok(Amount(if negative { -units } else { units }))
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let scale = SCALE.unsigned_abs();
+        write!(
+            fmt,
+            "{sign}{}.{:0width$}",
+            units / scale,
+            units % scale,
+            width = DECIMALS as usize
+        )
+    }
+}
+
+impl Serialize for Amount {
+    /// Textual formats get the exact decimal, binary formats the number of ten-thousandths
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_i64(self.0)
+        }
+    }
+}
+
+struct AmountVisitor;
+
+impl<'de> de::Visitor<'de> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("a decimal amount with up to four places past the decimal")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Amount, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Amount, E> {
+        Ok(Amount(value))
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    /// Amounts are read from their textual form so no precision is lost on the way, binary
+    /// formats contain the number of ten-thousandths
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(AmountVisitor)
+        } else {
+            deserializer.deserialize_i64(AmountVisitor)
+        }
+    }
+}
+
+/// Parse an amount written in a test
+#[cfg(test)]
+pub(crate) fn amount(s: &str) -> Amount {
+    s.parse().expect("Valid amount")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amounts_are_parsed_exactly() {
+        assert_eq!(amount("1"), Amount::from_units(10_000));
+        assert_eq!(amount("1.5"), Amount::from_units(15_000));
+        assert_eq!(amount("0.0001"), Amount::from_units(1));
+        assert_eq!(amount(".25"), Amount::from_units(2_500));
+        assert_eq!(amount("-3.1415"), Amount::from_units(-31_415));
+        assert_eq!(amount("  7.0  "), Amount::from_units(70_000));
+    }
+
+    #[test]
+    fn test_digits_past_the_precision_are_rounded_half_away_from_zero() {
+        assert_eq!(amount("0.00005"), Amount::from_units(1));
+        assert_eq!(amount("0.000049999"), Amount::ZERO);
+        assert_eq!(amount("1.000010001"), Amount::from_units(10_000));
+        assert_eq!(amount("-0.00005"), Amount::from_units(-1));
+    }
+
+    #[test]
+    fn test_invalid_amounts_cannot_be_parsed() {
+        assert_eq!("".parse::<Amount>(), Err(ParseAmountError::Empty));
+        assert_eq!(".".parse::<Amount>(), Err(ParseAmountError::Empty));
+        assert_eq!(
+            "1.0a".parse::<Amount>(),
+            Err(ParseAmountError::InvalidDigit)
+        );
+        assert_eq!("1e5".parse::<Amount>(), Err(ParseAmountError::InvalidDigit));
+        assert_eq!(
+            "922337203685477.5808".parse::<Amount>(),
+            Err(ParseAmountError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_amounts_are_displayed_with_4_digits_after_the_decimal() {
+        assert_eq!(amount("1.5").to_string(), "1.5000");
+        assert_eq!(amount("0").to_string(), "0.0000");
+        assert_eq!(amount("-0.0042").to_string(), "-0.0042");
+        assert_eq!(Amount::MIN.to_string(), "-922337203685477.5808");
+    }
+
+    #[test]
+    fn test_checked_arithmetic_reports_overflow() {
+        assert_eq!(
+            amount("1.1").checked_add(amount("2.2")),
+            Some(amount("3.3"))
+        );
+        assert_eq!(
+            amount("1.1").checked_sub(amount("2.2")),
+            Some(amount("-1.1"))
+        );
+        assert_eq!(Amount::MAX.checked_add(Amount::from_units(1)), None);
+        assert_eq!(Amount::MIN.checked_sub(Amount::from_units(1)), None);
+        assert_eq!(Amount::MIN.checked_neg(), None);
+    }
+}
@@ -1,16 +1,31 @@
-use crate::transactions::{Kind, Transaction};
-use error_stack::{Context, IntoReport, Report, Result, ResultExt};
-use serde::Serialize;
-use std::{collections::HashMap, fmt, io::Write};
+use crate::transactions::{Kind, Transaction, TxId};
+use error_stack::{Context, Report, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io::Write, str::FromStr};
 
+pub mod audit;
 pub mod client;
+pub mod dry_run;
+pub mod history;
+pub mod journal;
+pub mod rejections;
+pub mod report;
+pub mod sharded;
+pub mod snapshot;
+pub mod store;
+pub mod tx_ids;
+use audit::AuditEvent;
 use client::Amount;
+use history::History;
+use journal::Journal;
+use report::ReportFormat;
+use store::{AccountStore, MemoryAccountStore, MemoryTxStore, TxStore};
+use tx_ids::TxIdSet;
 
 pub type ClientId = u16;
 
 #[derive(Debug)]
 pub enum BankError {
-    InvalidInput,
     Other,
 }
 
@@ -22,9 +37,234 @@ fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+/// Reason why a single transaction was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxError {
+    InsufficientFunds,
+    Overflow,
+    UnknownTx,
+    ClientMismatch { owner: ClientId },
+    AccountLocked,
+    DuplicateTx,
+    NotDisputable,
+    IllegalTransition { from: TxState, kind: Kind },
+    Journal,
+    Storage,
+}
+
+impl Context for TxError {}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::InsufficientFunds => fmt.write_str("Insufficient available funds"),
+            TxError::Overflow => fmt.write_str("Funds would overflow"),
+            TxError::UnknownTx => fmt.write_str("Referenced transaction does not exist"),
+            TxError::ClientMismatch { owner } => {
+                write!(fmt, "Referenced transaction belongs to client {owner}")
+            }
+            TxError::AccountLocked => fmt.write_str("Account is locked"),
+            TxError::DuplicateTx => fmt.write_str("Transaction id is already used"),
+            TxError::NotDisputable => fmt.write_str("Referenced transaction cannot be disputed"),
+            TxError::IllegalTransition { from, kind } => {
+                write!(fmt, "{kind:?} is not allowed on a {from:?} transaction")
+            }
+            TxError::Journal => fmt.write_str("Transaction cannot be journaled"),
+            TxError::Storage => fmt.write_str("Account or transaction storage failed"),
+        }
+    }
+}
+
+fn account_error(err: client::AccountError) -> Report<TxError> {
+    let context = match err {
+        client::AccountError::InsufficientFunds(_) => TxError::InsufficientFunds,
+        client::AccountError::Overflow(_) => TxError::Overflow,
+    };
+    Report::new(err).change_context(context)
+}
+
+/// Outcome of applying a single transaction
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxOutcome {
+    Accepted,
+    Rejected(TxError),
+}
+
+impl<T> From<&Result<T, TxError>> for TxOutcome {
+    fn from(result: &Result<T, TxError>) -> TxOutcome {
+        match result {
+            Ok(_) => TxOutcome::Accepted,
+            Err(err) => TxOutcome::Rejected(err.current_context().clone()),
+        }
+    }
+}
+
+/// Dispute state of a deposit, withdrawal or fee
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxState {
+    Processed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+impl TxState {
+    /// The state reached by applying a dispute, resolve or chargeback, if that is a legal move
+    ///
+    /// A transaction can only be disputed once, and only a disputed transaction can be resolved
+    /// or charged back. Resolved and charged back transactions are final.
+    fn next(self, kind: Kind) -> Option<TxState> {
+        match (self, kind) {
+            (TxState::Processed, Kind::Dispute) => Some(TxState::Disputed),
+            (TxState::Disputed, Kind::Resolve) => Some(TxState::Resolved),
+            (TxState::Disputed, Kind::Chargeback) => Some(TxState::ChargedBack),
+            _ => None,
+        }
+    }
+}
+
+/// How disputes, resolves and chargebacks on withdrawals are handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisputePolicy {
+    /// Withdrawals cannot be disputed at all
+    NotDisputable,
+    /// A disputed withdrawal is credited back to the available funds while the held funds owe the
+    /// same amount, a chargeback keeps the credit and locks the account
+    #[default]
+    CreditBack,
+    /// A disputed withdrawal reserves the same amount from the available funds as held funds,
+    /// like a disputed deposit, a chargeback releases the reserve and credits the withdrawal back
+    ReserveHeld,
+}
+
+impl FromStr for DisputePolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<DisputePolicy, String> {
+        match policy {
+            "not-disputable" => Ok(DisputePolicy::NotDisputable),
+            "credit-back" => Ok(DisputePolicy::CreditBack),
+            "reserve-held" => Ok(DisputePolicy::ReserveHeld),
+            _ => Err(format!("Unknown dispute policy {policy}")),
+        }
+    }
+}
+
+/// Whether a fee can be charged when the available funds do not cover it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeePolicy {
+    /// A fee is rejected like a withdrawal when the available funds are insufficient
+    #[default]
+    RequireFunds,
+    /// A fee is always charged, even when the available funds become negative
+    AllowOverdraft,
+}
+
+impl FromStr for FeePolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<FeePolicy, String> {
+        match policy {
+            "require-funds" => Ok(FeePolicy::RequireFunds),
+            "allow-overdraft" => Ok(FeePolicy::AllowOverdraft),
+            _ => Err(format!("Unknown fee policy {policy}")),
+        }
+    }
+}
+
+/// Which transactions a locked account still accepts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockedAccountPolicy {
+    /// Every transaction is rejected
+    #[default]
+    RejectAll,
+    /// Only deposits are accepted
+    DepositsOnly,
+    /// Only disputes, resolves and chargebacks are accepted, so disputes opened before the lock
+    /// can still be finished
+    DisputesOnly,
+}
+
+impl FromStr for LockedAccountPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<LockedAccountPolicy, String> {
+        match policy {
+            "reject-all" => Ok(LockedAccountPolicy::RejectAll),
+            "deposits-only" => Ok(LockedAccountPolicy::DepositsOnly),
+            "disputes-only" => Ok(LockedAccountPolicy::DisputesOnly),
+            _ => Err(format!("Unknown locked account policy {policy}")),
+        }
+    }
+}
+
+impl LockedAccountPolicy {
+    fn accepts(self, kind: Kind) -> bool {
+        match self {
+            LockedAccountPolicy::RejectAll => false,
+            LockedAccountPolicy::DepositsOnly => kind == Kind::Deposit,
+            LockedAccountPolicy::DisputesOnly => {
+                matches!(kind, Kind::Dispute | Kind::Resolve | Kind::Chargeback)
+            }
+        }
+    }
+}
+
+/// A deposit, withdrawal or fee which can still be referenced by later disputes and adjustments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisputableTx {
+    pub client: ClientId,
+    pub kind: Kind,
+    pub amount: Amount,
+    pub state: TxState,
+}
+
+impl DisputableTx {
+    /// The amount moved by a dispute, resolve or chargeback referencing this transaction
+    fn disputed_amount(&self, policy: DisputePolicy) -> std::result::Result<Amount, TxError> {
+        match (self.kind, policy) {
+            (Kind::Withdrawal | Kind::Fee, DisputePolicy::NotDisputable) => {
+                Err(TxError::NotDisputable)
+            }
+            (Kind::Withdrawal | Kind::Fee, DisputePolicy::CreditBack) => {
+                self.amount.checked_neg().ok_or(TxError::Overflow)
+            }
+            _ => Ok(self.amount),
+        }
+    }
+}
+
+/// Everything a single transaction changes in the state of the bank
+#[derive(Default)]
+struct Change {
+    /// The client had no account yet
+    new_account: bool,
+    /// The transaction id is used for the first time
+    new_tx_id: bool,
+    /// New account state and dispute state of the referenced transaction when accepted
+    accepted: Option<(client::Account, Option<TxState>)>,
+    /// The accepted transaction locks the account
+    locks: bool,
+}
+
+impl Change {
+    fn is_empty(&self) -> bool {
+        !self.new_account && !self.new_tx_id && self.accepted.is_none()
+    }
+}
+
+/// The engine applying transactions, on top of in-memory storage unless other stores are given
 #[derive(Default)]
-pub struct Bank {
-    clients: HashMap<ClientId, client::Account>,
+pub struct Bank<A = MemoryAccountStore, T = MemoryTxStore> {
+    clients: A,
+    disputable_txs: T,
+    seen_tx_ids: TxIdSet,
+    dispute_policy: DisputePolicy,
+    locked_account_policy: LockedAccountPolicy,
+    fee_policy: FeePolicy,
+    journal: Option<Journal>,
+    history: Option<History>,
+    audit_trail: Vec<AuditEvent>,
 }
 
 #[derive(Debug, Serialize, PartialEq)]
@@ -37,6 +277,16 @@ pub struct AccountReport {
 }
 
 impl AccountReport {
+    fn from_account(client: ClientId, account: &client::Account) -> AccountReport {
+        AccountReport {
+            client,
+            available: account.get_available_funds(),
+            held: account.get_held_funds(),
+            total: account.get_total_funds(),
+            locked: account.is_locked(),
+        }
+    }
+
     pub fn new(
         client: ClientId,
         available: Amount,
@@ -54,130 +304,347 @@ pub fn new(
     }
 }
 
-fn group_transactions(transactions: Vec<Transaction>) -> HashMap<ClientId, Vec<Transaction>> {
-    let mut groups: HashMap<ClientId, Vec<Transaction>> = HashMap::new();
+impl Bank {
+    pub fn new() -> Bank {
+        Bank::default()
+    }
 
-    for tx in transactions {
-        let client_group = groups.entry(tx.client).or_insert(vec![]);
-        if !crate::transactions::is_disputable(&tx) {
-            if let None = client_group.iter().find(|x| x.tx == tx.tx) {
-                log::error!("[Tx {}] Invalid transaction from partner", tx.tx);
-                continue;
-            }
-        }
-        client_group.push(tx);
+    pub fn get_accounts_report(&self) -> Vec<AccountReport> {
+        self.try_get_accounts_report()
+            .expect("In-memory stores cannot fail")
     }
-    groups
 }
 
-fn get_amount_for_tx(tx: &Transaction, txs: &Vec<Transaction>) -> Option<f64> {
-    if let Some(disputable_tx) = txs.into_iter().find(|tx_from_client| {
-        (tx.tx == tx_from_client.tx) && crate::transactions::is_disputable(&tx_from_client)
-    }) {
-        if tx.kind == Kind::Withdrawal {
-            if let Some(amount) = disputable_tx.amount {
-                return Some(amount * -1.0);
+impl<A: AccountStore, T: TxStore> Bank<A, T> {
+    /// A bank keeping its accounts and disputable transactions in the given stores
+    ///
+    /// The stores must be empty, the dispute logic is the same for every kind of storage. Stores
+    /// which already hold records are reopened with [`Bank::open_store_dir`].
+    pub fn with_stores(clients: A, disputable_txs: T) -> Bank<A, T> {
+        Bank {
+            clients,
+            disputable_txs,
+            seen_tx_ids: TxIdSet::new(),
+            dispute_policy: DisputePolicy::default(),
+            locked_account_policy: LockedAccountPolicy::default(),
+            fee_policy: FeePolicy::default(),
+            journal: None,
+            history: None,
+            audit_trail: Vec::new(),
+        }
+    }
+
+    pub fn with_dispute_policy(mut self, dispute_policy: DisputePolicy) -> Bank<A, T> {
+        self.dispute_policy = dispute_policy;
+        self
+    }
+
+    pub fn with_locked_account_policy(
+        mut self,
+        locked_account_policy: LockedAccountPolicy,
+    ) -> Bank<A, T> {
+        self.locked_account_policy = locked_account_policy;
+        self
+    }
+
+    pub fn with_fee_policy(mut self, fee_policy: FeePolicy) -> Bank<A, T> {
+        self.fee_policy = fee_policy;
+        self
+    }
+
+    /// Apply all transactions strictly in the given order
+    ///
+    /// A rejected transaction does not stop the processing of the next ones. The outcome of every
+    /// transaction is returned in the same order as the input.
+    pub fn handle_transactions<I>(&mut self, transactions: I) -> Vec<TxOutcome>
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        transactions
+            .into_iter()
+            .map(|tx| TxOutcome::from(&self.apply(&tx)))
+            .collect()
+    }
+
+    /// Apply a single transaction on top of the current state
+    ///
+    /// Only the successful deposits and withdrawals are kept for later lookup, so memory grows
+    /// with the number of disputable transactions rather than with the size of the input.
+    /// Disputes, resolves and chargebacks must follow the dispute state of the referenced
+    /// transaction, otherwise they are rejected with [`TxError::IllegalTransition`].
+    ///
+    /// With a journal attached, every transaction which changes the state is journaled before
+    /// any account is touched.
+    pub fn apply(&mut self, tx: &Transaction) -> Result<(), TxError> {
+        self.apply_referring(tx, None)
+    }
+
+    /// Apply a transaction which refers to a transaction of `foreign_owner` when given, although
+    /// that transaction is kept by another bank
+    pub(crate) fn apply_referring(
+        &mut self,
+        tx: &Transaction,
+        foreign_owner: Option<ClientId>,
+    ) -> Result<(), TxError> {
+        if let Some(history) = &mut self.history {
+            history.next_position();
+        }
+        let result = self.plan(tx, foreign_owner).and_then(|(change, result)| {
+            self.write_journal(tx, &change)?;
+            self.commit(tx, change)?;
+            result
+        });
+        match &result {
+            Ok(()) => log::info!("{tx:?} successful"),
+            // a partner referring to transactions of other clients needs to be escalated
+            Err(err) if matches!(err.current_context(), TxError::ClientMismatch { .. }) => {
+                log::error!("{tx:?} failed. {}", err.current_context())
             }
+            Err(err) => log::warn!("{tx:?} failed. {}", err.current_context()),
         }
-        return disputable_tx.amount;
+        result
     }
-    None
-}
 
-fn handle_tx(
-    tx: &Transaction,
-    account: &mut client::Account,
-    txs: &Vec<Transaction>,
-) -> Result<(), client::AccountError> {
-    match tx.kind {
-        Kind::Withdrawal => {
-            let amount = tx.amount.expect("Should be checked when parsing");
-            return account.withdrawal(amount).map_err(|err| Report::new(err));
+    /// Work out what a transaction changes without touching the state
+    ///
+    /// Only a failing store is returned as outer error, the inner result is the outcome of the
+    /// transaction itself.
+    fn plan(
+        &self,
+        tx: &Transaction,
+        foreign_owner: Option<ClientId>,
+    ) -> Result<(Change, Result<(), TxError>), TxError> {
+        let account = self
+            .clients
+            .account(tx.client)
+            .change_context(TxError::Storage)?;
+        let mut change = Change {
+            new_account: account.is_none(),
+            ..Change::default()
+        };
+
+        // ids of rejected deposits and withdrawals count as used as well
+        if crate::transactions::has_own_id(tx) {
+            if self.seen_tx_ids.contains(tx.tx) {
+                let err = Report::new(TxError::DuplicateTx)
+                    .attach_printable(format!("[Tx {}] was already seen in the input", tx.tx));
+                return Ok((change, Err(err)));
+            }
+            change.new_tx_id = true;
         }
-        Kind::Deposit => account.deposit(tx.amount.expect("Should be checked when parsing")),
-        _ => {
-            if let Some(amount) = get_amount_for_tx(&tx, &txs) {
-                match tx.kind {
-                    Kind::Dispute => account.dispute(amount),
-                    Kind::Resolve => account.resolve(amount),
-                    Kind::Chargeback => account.chargeback(amount),
-                    _ => {}
-                };
+
+        let was_locked = account.as_ref().is_some_and(client::Account::is_locked);
+        match self.plan_accepted(tx, account.unwrap_or_default(), foreign_owner) {
+            Ok(accepted) => {
+                change.locks = accepted.0.is_locked() && !was_locked;
+                change.accepted = Some(accepted);
+                Ok((change, Ok(())))
             }
+            Err(err) if *err.current_context() == TxError::Storage => Err(err),
+            Err(err) => Ok((change, Err(err))),
         }
     }
-    Ok(())
-}
 
-impl Bank {
-    pub fn new() -> Bank {
-        Bank::default()
+    fn plan_accepted(
+        &self,
+        tx: &Transaction,
+        mut account: client::Account,
+        foreign_owner: Option<ClientId>,
+    ) -> Result<(client::Account, Option<TxState>), TxError> {
+        if account.is_locked() && !self.locked_account_policy.accepts(tx.kind) {
+            return Err(Report::new(TxError::AccountLocked))
+                .attach_printable(format!("Client {} is locked", tx.client));
+        }
+
+        let state = match tx.kind {
+            Kind::Deposit | Kind::Interest => {
+                account
+                    .deposit(tx.amount.expect("Should be checked when parsing"))
+                    .map_err(account_error)?;
+                None
+            }
+            Kind::Withdrawal => {
+                account
+                    .withdrawal(tx.amount.expect("Should be checked when parsing"))
+                    .map_err(account_error)?;
+                None
+            }
+            Kind::Fee => {
+                let amount = tx.amount.expect("Should be checked when parsing");
+                match self.fee_policy {
+                    FeePolicy::RequireFunds => account.withdrawal(amount),
+                    FeePolicy::AllowOverdraft => account.overdraw(amount),
+                }
+                .map_err(account_error)?;
+                None
+            }
+            Kind::Adjustment => {
+                self.referenced_tx(tx, foreign_owner)?;
+                account
+                    .adjust(tx.amount.expect("Should be checked when parsing"))
+                    .map_err(account_error)?;
+                None
+            }
+            Kind::Dispute | Kind::Resolve | Kind::Chargeback => {
+                let disputable_tx = self.referenced_tx(tx, foreign_owner)?;
+                let amount = disputable_tx
+                    .disputed_amount(self.dispute_policy)
+                    .map_err(Report::new)?;
+                let state = disputable_tx.state.next(tx.kind).ok_or_else(|| {
+                    Report::new(TxError::IllegalTransition {
+                        from: disputable_tx.state,
+                        kind: tx.kind,
+                    })
+                })?;
+                match (tx.kind, disputable_tx.kind, self.dispute_policy) {
+                    (Kind::Dispute, ..) => account.dispute(amount),
+                    (Kind::Resolve, ..) => account.resolve(amount),
+                    // the reserve is released and the withdrawn amount returned
+                    (_, Kind::Withdrawal | Kind::Fee, DisputePolicy::ReserveHeld) => {
+                        account.chargeback_reserved_withdrawal(amount)
+                    }
+                    _ => account.chargeback(amount),
+                }
+                .map_err(account_error)?;
+                Some(state)
+            }
+        };
+        Ok((account, state))
     }
 
-    pub fn handle_transactions(&mut self, transactions: Vec<Transaction>) -> Result<(), BankError> {
-        for (client_id, txs) in &mut group_transactions(transactions) {
-            let mut account = self
-                .clients
-                .entry(*client_id)
-                .or_insert(client::Account::new());
-
-            if account.is_locked() {
-                log::info!("Client {client_id} is locked and cannot accept any transactions");
-                continue;
-            }
-
-            //--> replace this block when `Vec::drain_filter` becomes stable
-            let mut i = 0;
-            while i < txs.len() {
-                let tx = &txs[i];
-                if let Err(err) = handle_tx(tx, &mut account, &txs) {
-                    log::warn!("{tx:?} failed. {err}");
-                    txs.remove(i);
-                } else {
-                    log::info!("{tx:?} successful");
-                    i += 1;
+    /// The earlier transaction of the same client which the transaction refers to by its id
+    ///
+    /// A transaction of a `foreign_owner` is kept by another bank, which only tells its owner.
+    fn referenced_tx(
+        &self,
+        tx: &Transaction,
+        foreign_owner: Option<ClientId>,
+    ) -> Result<DisputableTx, TxError> {
+        let owner = match foreign_owner {
+            Some(owner) => owner,
+            None => {
+                let disputable_tx = self
+                    .disputable_txs
+                    .disputable_tx(tx.tx)
+                    .change_context(TxError::Storage)?
+                    .ok_or_else(|| Report::new(TxError::UnknownTx))
+                    .attach_printable(format!("[Tx {}] Invalid transaction from partner", tx.tx))?;
+                if disputable_tx.client == tx.client {
+                    return Ok(disputable_tx);
                 }
+                disputable_tx.client
             }
-            //<--
+        };
+        Err(Report::new(TxError::ClientMismatch { owner })).attach_printable(format!(
+            "[Tx {}] Client {} refers to a transaction of another client",
+            tx.tx, tx.client
+        ))
+    }
+
+    fn write_journal(&mut self, tx: &Transaction, change: &Change) -> Result<(), TxError> {
+        let policies = self.policies();
+        match &mut self.journal {
+            Some(journal) => journal
+                .record(tx, policies, !change.is_empty())
+                .change_context(TxError::Journal),
+            None => Ok(()),
         }
-        Ok(())
     }
 
-    pub fn get_accounts_report(&self) -> Vec<AccountReport> {
-        let mut reports = Vec::new();
-        for client in &self.clients {
-            let report = AccountReport {
-                client: *client.0,
-                available: client.1.get_available_funds(),
-                held: client.1.get_held_funds(),
-                total: client.1.get_total_funds(),
-                locked: client.1.is_locked(),
-            };
-            reports.push(report);
+    fn commit(&mut self, tx: &Transaction, change: Change) -> Result<(), TxError> {
+        if change.new_tx_id {
+            self.seen_tx_ids.insert(tx.tx);
+        }
+        let Some((account, state)) = change.accepted else {
+            // even rejected transactions open an account
+            if change.new_account {
+                self.clients
+                    .store_account(tx.client, client::Account::default())
+                    .change_context(TxError::Storage)?;
+            }
+            return Ok(());
+        };
+        self.clients
+            .store_account(tx.client, account.clone())
+            .change_context(TxError::Storage)?;
+        // only a change which is stored shows up in a statement
+        if let Some(history) = &mut self.history {
+            history.record(tx, &account);
+        }
+        if change.locks {
+            self.audit_trail.push(AuditEvent::locked(tx));
         }
-        reports.sort_by(|a, b| a.client.cmp(&b.client));
-        reports
+
+        let disputable_tx = match state {
+            Some(state) => DisputableTx {
+                state,
+                ..self
+                    .disputable_txs
+                    .disputable_tx(tx.tx)
+                    .change_context(TxError::Storage)?
+                    .expect("Checked while planning")
+            },
+            None if crate::transactions::is_disputable(tx) => DisputableTx {
+                client: tx.client,
+                kind: tx.kind,
+                amount: tx.amount.expect("Should be checked when parsing"),
+                state: TxState::Processed,
+            },
+            None => return Ok(()),
+        };
+        self.disputable_txs
+            .store_disputable_tx(tx.tx, disputable_tx)
+            .change_context(TxError::Storage)
     }
 
+    pub fn try_get_accounts_report(&self) -> Result<Vec<AccountReport>, BankError> {
+        let mut reports: Vec<AccountReport> = self
+            .clients
+            .accounts()
+            .change_context(BankError::Other)?
+            .into_iter()
+            .map(|(client, account)| AccountReport::from_account(client, &account))
+            .collect();
+        reports.sort_by_key(|report| report.client);
+        Ok(reports)
+    }
+
+    pub fn try_get_account_report(
+        &self,
+        client: ClientId,
+    ) -> Result<Option<AccountReport>, BankError> {
+        let account = self
+            .clients
+            .account(client)
+            .change_context(BankError::Other)?;
+        Ok(account.map(|account| AccountReport::from_account(client, &account)))
+    }
+
+    /// An accepted deposit or withdrawal together with its dispute state
+    pub fn try_get_disputable_tx(&self, tx: TxId) -> Result<Option<DisputableTx>, BankError> {
+        self.disputable_txs
+            .disputable_tx(tx)
+            .change_context(BankError::Other)
+    }
+
+    /// Write the accounts report as comma-delimited CSV with a header row
     pub fn output_accounts_report<W: Write>(&self, writer: &mut W) -> Result<(), BankError> {
-        let mut writer = csv::WriterBuilder::new()
-            .delimiter(b',')
-            .from_writer(writer);
+        self.output_accounts_report_as(writer, ReportFormat::default())
+    }
 
-        for report in self.get_accounts_report() {
-            writer
-                .serialize(&report)
-                .report()
-                .change_context(BankError::Other)
-                .attach_printable(format!("Failed to serialize account report {report:?}"))?;
-        }
-        writer.flush().report().change_context(BankError::Other)
+    pub fn output_accounts_report_as<W: Write>(
+        &self,
+        writer: &mut W,
+        format: ReportFormat,
+    ) -> Result<(), BankError> {
+        report::write_report(&self.try_get_accounts_report()?, format, writer)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::amount::amount;
 
     #[test]
     fn test_that_bank_starts_with_zero_clients() {
@@ -187,7 +654,7 @@ fn test_that_bank_starts_with_zero_clients() {
     }
 
     #[test]
-    fn test_client_report_for_basic_transactions() -> Result<(), BankError> {
+    fn test_client_report_for_basic_transactions() {
         let mut bank = Bank::new();
 
         let transactions = vec![
@@ -195,52 +662,350 @@ fn test_client_report_for_basic_transactions() -> Result<(), BankError> {
                 kind: Kind::Deposit,
                 client: 1,
                 tx: 1,
-                amount: Some(1.0),
+                amount: Some(amount("1.0")),
             },
             Transaction {
                 kind: Kind::Deposit,
                 client: 2,
                 tx: 2,
-                amount: Some(2.0),
+                amount: Some(amount("2.0")),
             },
             Transaction {
                 kind: Kind::Deposit,
                 client: 1,
                 tx: 3,
-                amount: Some(2.0),
+                amount: Some(amount("2.0")),
             },
             Transaction {
                 kind: Kind::Withdrawal,
                 client: 1,
                 tx: 4,
-                amount: Some(1.5),
+                amount: Some(amount("1.5")),
             },
             Transaction {
                 kind: Kind::Withdrawal,
                 client: 2,
                 tx: 5,
-                amount: Some(3.0),
+                amount: Some(amount("3.0")),
             },
         ];
-        bank.handle_transactions(transactions)?;
+        let outcomes = bank.handle_transactions(transactions);
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                TxOutcome::Rejected(TxError::InsufficientFunds),
+            ]
+        );
         let expected = vec![
             AccountReport {
                 client: 1,
-                available: 1.5,
-                held: 0.0,
-                total: 1.5,
+                available: amount("1.5"),
+                held: amount("0.0"),
+                total: amount("1.5"),
                 locked: false,
             },
             AccountReport {
                 client: 2,
-                available: 2.0,
-                held: 0.0,
-                total: 2.0,
+                available: amount("2.0"),
+                held: amount("0.0"),
+                total: amount("2.0"),
                 locked: false,
             },
         ];
 
         assert_eq!(bank.get_accounts_report(), expected);
-        Ok(())
+    }
+
+    #[test]
+    fn test_apply_keeps_only_successful_disputable_transactions() {
+        let mut bank = Bank::new();
+
+        bank.apply(&Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))))
+            .expect("Deposit failed");
+        assert!(bank
+            .apply(&Transaction::new(
+                Kind::Withdrawal,
+                1,
+                2,
+                Some(amount("5.0"))
+            ))
+            .is_err());
+        bank.apply(&Transaction::new(Kind::Dispute, 1, 1, None))
+            .expect("Dispute failed");
+
+        assert_eq!(bank.disputable_txs.len(), 1);
+        assert_eq!(
+            bank.get_accounts_report(),
+            vec![AccountReport::new(
+                1,
+                Amount::ZERO,
+                amount("1.0"),
+                amount("1.0"),
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn test_apply_rejects_disputes_on_transactions_of_other_clients() {
+        let mut bank = Bank::new();
+
+        bank.apply(&Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))))
+            .expect("Deposit failed");
+        let err = bank
+            .apply(&Transaction::new(Kind::Dispute, 2, 1, None))
+            .unwrap_err();
+        assert_eq!(*err.current_context(), TxError::ClientMismatch { owner: 1 });
+
+        assert_eq!(
+            bank.get_accounts_report(),
+            vec![
+                AccountReport::new(1, amount("1.0"), Amount::ZERO, amount("1.0"), false),
+                AccountReport::new(2, Amount::ZERO, Amount::ZERO, Amount::ZERO, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_a_transaction_cannot_be_disputed_twice() {
+        let mut bank = Bank::new();
+
+        bank.apply(&Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))))
+            .expect("Deposit failed");
+        bank.apply(&Transaction::new(Kind::Dispute, 1, 1, None))
+            .expect("Dispute failed");
+
+        let err = bank
+            .apply(&Transaction::new(Kind::Dispute, 1, 1, None))
+            .unwrap_err();
+        assert_eq!(
+            *err.current_context(),
+            TxError::IllegalTransition {
+                from: TxState::Disputed,
+                kind: Kind::Dispute
+            }
+        );
+        assert_eq!(
+            bank.get_accounts_report(),
+            vec![AccountReport::new(
+                1,
+                Amount::ZERO,
+                amount("1.0"),
+                amount("1.0"),
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn test_only_disputed_transactions_can_be_resolved_or_charged_back() {
+        let mut bank = Bank::new();
+
+        bank.apply(&Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))))
+            .expect("Deposit failed");
+
+        for kind in [Kind::Resolve, Kind::Chargeback] {
+            let err = bank.apply(&Transaction::new(kind, 1, 1, None)).unwrap_err();
+            assert_eq!(
+                *err.current_context(),
+                TxError::IllegalTransition {
+                    from: TxState::Processed,
+                    kind
+                }
+            );
+        }
+        assert_eq!(
+            bank.get_accounts_report(),
+            vec![AccountReport::new(
+                1,
+                amount("1.0"),
+                Amount::ZERO,
+                amount("1.0"),
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn test_a_resolved_transaction_cannot_be_charged_back() {
+        let mut bank = Bank::new();
+
+        bank.apply(&Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))))
+            .expect("Deposit failed");
+        bank.apply(&Transaction::new(Kind::Dispute, 1, 1, None))
+            .expect("Dispute failed");
+        bank.apply(&Transaction::new(Kind::Resolve, 1, 1, None))
+            .expect("Resolve failed");
+
+        let err = bank
+            .apply(&Transaction::new(Kind::Chargeback, 1, 1, None))
+            .unwrap_err();
+        assert_eq!(
+            *err.current_context(),
+            TxError::IllegalTransition {
+                from: TxState::Resolved,
+                kind: Kind::Chargeback
+            }
+        );
+        assert_eq!(bank.disputable_txs[&1].state, TxState::Resolved);
+        assert_eq!(
+            bank.get_accounts_report(),
+            vec![AccountReport::new(
+                1,
+                amount("1.0"),
+                Amount::ZERO,
+                amount("1.0"),
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn test_disputes_on_unknown_transactions_are_rejected() {
+        let mut bank = Bank::new();
+
+        let err = bank
+            .apply(&Transaction::new(Kind::Dispute, 1, 1, None))
+            .unwrap_err();
+        assert_eq!(*err.current_context(), TxError::UnknownTx);
+    }
+
+    #[test]
+    fn test_a_locked_account_rejects_every_transaction() {
+        let mut bank = Bank::new();
+
+        let outcomes = bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))),
+            Transaction::new(Kind::Deposit, 1, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 1, None),
+            Transaction::new(Kind::Chargeback, 1, 1, None),
+            Transaction::new(Kind::Deposit, 1, 3, Some(amount("1.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 4, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 2, None),
+        ]);
+
+        assert_eq!(
+            outcomes[4..],
+            [
+                TxOutcome::Rejected(TxError::AccountLocked),
+                TxOutcome::Rejected(TxError::AccountLocked),
+                TxOutcome::Rejected(TxError::AccountLocked),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_transaction_ids_are_rejected() {
+        let mut bank = Bank::new();
+
+        let outcomes = bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))),
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("2.0"))),
+            Transaction::new(Kind::Withdrawal, 2, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Deposit, 2, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 1, None),
+        ]);
+
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Accepted,
+                TxOutcome::Rejected(TxError::DuplicateTx),
+                TxOutcome::Rejected(TxError::InsufficientFunds),
+                TxOutcome::Rejected(TxError::DuplicateTx),
+                TxOutcome::Accepted,
+            ]
+        );
+        assert_eq!(
+            bank.get_accounts_report(),
+            vec![
+                AccountReport::new(1, Amount::ZERO, amount("1.0"), amount("1.0"), false),
+                AccountReport::new(2, Amount::ZERO, Amount::ZERO, Amount::ZERO, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fees_overdraw_an_account_only_with_the_allow_overdraft_policy() {
+        let transactions = vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))),
+            Transaction::new(Kind::Fee, 1, 2, Some(amount("2.0"))),
+        ];
+        for (policy, outcome, available) in [
+            (
+                FeePolicy::RequireFunds,
+                TxOutcome::Rejected(TxError::InsufficientFunds),
+                amount("1.0"),
+            ),
+            (
+                FeePolicy::AllowOverdraft,
+                TxOutcome::Accepted,
+                amount("-1.0"),
+            ),
+        ] {
+            let mut bank = Bank::new().with_fee_policy(policy);
+            let outcomes = bank.handle_transactions(transactions.clone());
+
+            assert_eq!(outcomes, vec![TxOutcome::Accepted, outcome], "{policy:?}");
+            assert_eq!(
+                bank.get_accounts_report(),
+                vec![AccountReport::new(
+                    1,
+                    available,
+                    Amount::ZERO,
+                    available,
+                    false
+                )],
+                "{policy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_interest_fees_and_adjustments_follow_their_own_rules() {
+        let mut bank = Bank::new();
+
+        let outcomes = bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+            Transaction::new(Kind::Interest, 1, 2, Some(amount("0.5"))),
+            Transaction::new(Kind::Fee, 1, 3, Some(amount("1.0"))),
+            Transaction::new(Kind::Adjustment, 1, 1, Some(amount("-2.0"))),
+            Transaction::new(Kind::Adjustment, 1, 1, Some(amount("0.5"))),
+            Transaction::new(Kind::Adjustment, 1, 9, Some(amount("1.0"))),
+            Transaction::new(Kind::Adjustment, 2, 1, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 2, None),
+            Transaction::new(Kind::Dispute, 1, 3, None),
+            Transaction::new(Kind::Interest, 1, 2, Some(amount("1.0"))),
+        ]);
+
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                // adjustments refer to a transaction instead of using an id of their own
+                TxOutcome::Accepted,
+                TxOutcome::Rejected(TxError::UnknownTx),
+                TxOutcome::Rejected(TxError::ClientMismatch { owner: 1 }),
+                // interest cannot be disputed
+                TxOutcome::Rejected(TxError::UnknownTx),
+                TxOutcome::Accepted,
+                TxOutcome::Rejected(TxError::DuplicateTx),
+            ]
+        );
+        // the disputed fee is credited back like a withdrawal
+        assert_eq!(
+            bank.get_accounts_report(),
+            vec![
+                AccountReport::new(1, amount("4.0"), amount("-1.0"), amount("3.0"), false),
+                AccountReport::new(2, Amount::ZERO, Amount::ZERO, Amount::ZERO, false),
+            ]
+        );
     }
 }
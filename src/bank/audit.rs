@@ -0,0 +1,187 @@
+use super::{
+    store::{AccountStore, TxStore},
+    Bank, ClientId,
+};
+use crate::transactions::{Transaction, TxId};
+use error_stack::{Context, Report, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Reason why an administrative operation was refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminError {
+    UnknownAccount,
+    NotLocked,
+    MissingDetails,
+    Journal,
+    Storage,
+}
+
+impl Context for AdminError {}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminError::UnknownAccount => fmt.write_str("Account does not exist"),
+            AdminError::NotLocked => fmt.write_str("Account is not locked"),
+            AdminError::MissingDetails => fmt.write_str("An operator and a reason are required"),
+            AdminError::Journal => fmt.write_str("Unlock cannot be journaled"),
+            AdminError::Storage => fmt.write_str("Account storage failed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Locked,
+    Unlocked,
+}
+
+/// Lock or unlock of an account, in the order they happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub client: ClientId,
+    pub action: AuditAction,
+    /// Chargeback which locked the account
+    pub tx: Option<TxId>,
+    /// Operator who unlocked the account
+    pub operator: Option<String>,
+    pub reason: String,
+}
+
+impl AuditEvent {
+    pub(super) fn locked(chargeback: &Transaction) -> AuditEvent {
+        AuditEvent {
+            client: chargeback.client,
+            action: AuditAction::Locked,
+            tx: Some(chargeback.tx),
+            operator: None,
+            reason: "chargeback".to_owned(),
+        }
+    }
+}
+
+impl<A: AccountStore, T: TxStore> Bank<A, T> {
+    /// Lift the lock of an account after a chargeback, the funds stay as they are
+    ///
+    /// The operator and the reason are kept in the audit trail. With a journal attached the unlock
+    /// is journaled first, so a recovery unlocks the account at the same point.
+    pub fn unlock_account(
+        &mut self,
+        client: ClientId,
+        operator: &str,
+        reason: &str,
+    ) -> Result<(), AdminError> {
+        if operator.trim().is_empty() || reason.trim().is_empty() {
+            return Err(Report::new(AdminError::MissingDetails));
+        }
+        let mut account = self
+            .clients
+            .account(client)
+            .change_context(AdminError::Storage)?
+            .ok_or_else(|| {
+                Report::new(AdminError::UnknownAccount)
+                    .attach_printable(format!("Client {client} has no account"))
+            })?;
+        if !account.is_locked() {
+            return Err(Report::new(AdminError::NotLocked)
+                .attach_printable(format!("Client {client} is not locked")));
+        }
+
+        if let Some(journal) = &mut self.journal {
+            journal
+                .record_unlock(client, operator, reason)
+                .change_context(AdminError::Journal)?;
+        }
+        account.unlock();
+        self.clients
+            .store_account(client, account)
+            .change_context(AdminError::Storage)?;
+        log::info!("Client {client} unlocked by {operator}: {reason}");
+        self.audit_trail.push(AuditEvent {
+            client,
+            action: AuditAction::Unlocked,
+            tx: None,
+            operator: Some(operator.to_owned()),
+            reason: reason.to_owned(),
+        });
+        Ok(())
+    }
+
+    /// Every lock and unlock of an account, oldest first
+    pub fn audit_trail(&self) -> &[AuditEvent] {
+        &self.audit_trail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::transactions::Kind;
+
+    #[test]
+    fn test_an_unlocked_account_accepts_transactions_again() {
+        let mut bank = Bank::new();
+        bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+            Transaction::new(Kind::Deposit, 1, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 2, None),
+            Transaction::new(Kind::Chargeback, 1, 2, None),
+        ]);
+        assert_eq!(
+            *bank
+                .unlock_account(1, "", "customer verified")
+                .unwrap_err()
+                .current_context(),
+            AdminError::MissingDetails
+        );
+        assert_eq!(
+            *bank
+                .unlock_account(2, "ops-7", "customer verified")
+                .unwrap_err()
+                .current_context(),
+            AdminError::UnknownAccount
+        );
+
+        bank.unlock_account(1, "ops-7", "customer verified")
+            .expect("Unlocking failed");
+        assert!(!bank.clients[&1].is_locked());
+        assert!(bank
+            .apply(&Transaction::new(
+                Kind::Withdrawal,
+                1,
+                3,
+                Some(amount("5.0"))
+            ))
+            .is_ok());
+        assert_eq!(
+            *bank
+                .unlock_account(1, "ops-7", "customer verified")
+                .unwrap_err()
+                .current_context(),
+            AdminError::NotLocked
+        );
+
+        assert_eq!(
+            bank.audit_trail(),
+            &[
+                AuditEvent {
+                    client: 1,
+                    action: AuditAction::Locked,
+                    tx: Some(2),
+                    operator: None,
+                    reason: "chargeback".to_owned(),
+                },
+                AuditEvent {
+                    client: 1,
+                    action: AuditAction::Unlocked,
+                    tx: None,
+                    operator: Some("ops-7".to_owned()),
+                    reason: "customer verified".to_owned(),
+                },
+            ]
+        );
+    }
+}
@@ -1,14 +1,11 @@
+pub use crate::amount::Amount;
+use serde::{Deserialize, Serialize};
 use std::{error::Error, fmt};
 
-pub type Amount = f64;
-
-fn round_at_4_dec(x: Amount) -> Amount {
-    (x * 10000.0).round() / 10000.0
-}
-
 #[derive(Debug, PartialEq)]
 pub enum AccountError {
     InsufficientFunds(String),
+    Overflow(String),
 }
 
 impl Error for AccountError {}
@@ -19,7 +16,11 @@ fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
-#[derive(Default)]
+fn overflow(operation: &str) -> AccountError {
+    AccountError::Overflow(format!("Funds overflow when applying {operation}"))
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Account {
     available_funds: Amount,
     held_funds: Amount,
@@ -32,32 +33,57 @@ pub fn new() -> Account {
     }
 
     pub fn get_available_funds(&self) -> Amount {
-        round_at_4_dec(self.available_funds)
+        self.available_funds
     }
 
     pub fn get_held_funds(&self) -> Amount {
-        round_at_4_dec(self.held_funds)
+        self.held_funds
     }
 
     pub fn get_total_funds(&self) -> Amount {
-        round_at_4_dec(self.available_funds + self.held_funds)
+        self.available_funds
+            .checked_add(self.held_funds)
+            .expect("Total funds are kept in range by every account operation")
     }
 
     pub fn is_locked(&self) -> bool {
         self.locked
     }
 
+    /// Replace the funds only when the new total can be represented as well
+    fn set_funds(
+        &mut self,
+        available_funds: Option<Amount>,
+        held_funds: Option<Amount>,
+        operation: &str,
+    ) -> Result<(), AccountError> {
+        match (available_funds, held_funds) {
+            (Some(available_funds), Some(held_funds))
+                if available_funds.checked_add(held_funds).is_some() =>
+            {
+                self.available_funds = available_funds;
+                self.held_funds = held_funds;
+                Ok(())
+            }
+            _ => Err(overflow(operation)),
+        }
+    }
+
     /// A credit to the client's asset account with an amount
     ///
     /// # Examples
     /// ```
     /// use tx_engine::client::Account;
     /// let mut account = Account::new();
-    /// account.deposit(2.0);
-    /// assert_eq!(account.get_available_funds(), 2.0);
+    /// account.deposit("2.0".parse().unwrap()).unwrap();
+    /// assert_eq!(account.get_available_funds(), "2.0".parse().unwrap());
     /// ```
-    pub fn deposit(&mut self, amount: Amount) {
-        self.available_funds += amount;
+    pub fn deposit(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.set_funds(
+            self.available_funds.checked_add(amount),
+            Some(self.held_funds),
+            "deposit",
+        )
     }
 
     /// A debit to client's asset account with an amount
@@ -66,151 +92,273 @@ pub fn deposit(&mut self, amount: Amount) {
     /// ```
     /// use tx_engine::client::Account;
     /// let mut account = Account::new();
-    /// account.deposit(2.0);
-    /// account.withdrawal(1.0);
-    /// assert_eq!(account.get_available_funds(), 1.0);
+    /// account.deposit("2.0".parse().unwrap()).unwrap();
+    /// account.withdrawal("1.0".parse().unwrap()).unwrap();
+    /// assert_eq!(account.get_available_funds(), "1.0".parse().unwrap());
     /// ```
     pub fn withdrawal(&mut self, amount: Amount) -> Result<(), AccountError> {
-        if (self.available_funds - amount) < 0.0 {
+        let available_funds = self
+            .available_funds
+            .checked_sub(amount)
+            .ok_or_else(|| overflow("withdrawal"))?;
+        if available_funds.is_negative() {
             return Err(AccountError::InsufficientFunds(
                 "Insufficient funds to withdraw".to_owned(),
             ));
         }
 
-        self.available_funds -= amount;
-        Ok(())
+        self.set_funds(Some(available_funds), Some(self.held_funds), "withdrawal")
+    }
+
+    /// A debit which is allowed to leave the available funds negative
+    pub fn overdraw(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.set_funds(
+            self.available_funds.checked_sub(amount),
+            Some(self.held_funds),
+            "overdraw",
+        )
+    }
+
+    /// A signed correction of the available funds, which may leave them negative
+    pub fn adjust(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.set_funds(
+            self.available_funds.checked_add(amount),
+            Some(self.held_funds),
+            "adjustment",
+        )
     }
 
     /// An amount under dispute which becomes held
-    pub fn dispute(&mut self, amount: Amount) {
-        self.available_funds -= amount;
-        self.held_funds += amount;
+    pub fn dispute(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.set_funds(
+            self.available_funds.checked_sub(amount),
+            self.held_funds.checked_add(amount),
+            "dispute",
+        )
     }
 
     /// A resolution to a dispute which releases the held funds
-    pub fn resolve(&mut self, amount: Amount) {
-        self.available_funds += amount;
-        self.held_funds -= amount;
+    pub fn resolve(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.set_funds(
+            self.available_funds.checked_add(amount),
+            self.held_funds.checked_sub(amount),
+            "resolve",
+        )
     }
 
     /// A resolution to a dispute that locks the account
-    pub fn chargeback(&mut self, amount: Amount) {
-        self.held_funds -= amount;
+    pub fn chargeback(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.set_funds(
+            Some(self.available_funds),
+            self.held_funds.checked_sub(amount),
+            "chargeback",
+        )?;
+        self.locked = true;
+        Ok(())
+    }
+
+    /// A chargeback of a withdrawal whose disputed amount is held in reserve, which releases the
+    /// reserve and credits the withdrawn amount back before locking the account
+    pub fn chargeback_reserved_withdrawal(&mut self, amount: Amount) -> Result<(), AccountError> {
+        self.set_funds(
+            self.available_funds
+                .checked_add(amount)
+                .and_then(|available| available.checked_add(amount)),
+            self.held_funds.checked_sub(amount),
+            "chargeback",
+        )?;
         self.locked = true;
+        Ok(())
+    }
+
+    /// Lift the lock set by a chargeback, the funds stay as they are
+    pub fn unlock(&mut self) {
+        self.locked = false;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::amount::amount;
 
     #[test]
     fn test_accounts_are_initialized_with_zero_funds_and_are_unlocked() {
         let account = Account::new();
-        assert_eq!(account.get_available_funds(), 0.0);
-        assert_eq!(account.get_held_funds(), 0.0);
-        assert_eq!(account.get_total_funds(), 0.0);
-        assert_eq!(account.is_locked(), false);
+        assert_eq!(account.get_available_funds(), Amount::ZERO);
+        assert_eq!(account.get_held_funds(), Amount::ZERO);
+        assert_eq!(account.get_total_funds(), Amount::ZERO);
+        assert!(!account.is_locked());
     }
 
     #[test]
-    fn test_account_deposits_increases_funds() {
+    fn test_account_deposits_increases_funds() -> Result<(), AccountError> {
         let mut account = Account::new();
-        account.deposit(1.0);
-        account.deposit(2.0);
-        account.deposit(3.0);
+        account.deposit(amount("1.0"))?;
+        account.deposit(amount("2.0"))?;
+        account.deposit(amount("3.0"))?;
 
-        assert_eq!(account.get_available_funds(), 6.0);
-        assert_eq!(account.get_total_funds(), 6.0);
-        assert_eq!(account.get_held_funds(), 0.0);
+        assert_eq!(account.get_available_funds(), amount("6.0"));
+        assert_eq!(account.get_total_funds(), amount("6.0"));
+        assert_eq!(account.get_held_funds(), Amount::ZERO);
+        Ok(())
     }
 
     #[test]
     fn test_account_withdrawal_decreases_funds() -> Result<(), AccountError> {
         let mut account = Account::new();
-        account.deposit(6.0);
+        account.deposit(amount("6.0"))?;
 
-        account.withdrawal(3.0)?;
-        account.withdrawal(2.0)?;
-        assert_eq!(account.get_available_funds(), 1.0);
-        assert_eq!(account.get_total_funds(), 1.0);
-        assert_eq!(account.get_held_funds(), 0.0);
+        account.withdrawal(amount("3.0"))?;
+        account.withdrawal(amount("2.0"))?;
+        assert_eq!(account.get_available_funds(), amount("1.0"));
+        assert_eq!(account.get_total_funds(), amount("1.0"));
+        assert_eq!(account.get_held_funds(), Amount::ZERO);
         Ok(())
     }
 
     #[test]
-    fn test_account_dispute_descreases_available_funds_and_increases_held_funds() {
+    fn test_account_overdraw_and_adjust_may_leave_negative_funds() -> Result<(), AccountError> {
         let mut account = Account::new();
-        account.deposit(6.0);
+        account.deposit(amount("1.0"))?;
+
+        account.overdraw(amount("3.0"))?;
+        assert_eq!(account.get_available_funds(), amount("-2.0"));
+        account.adjust(amount("-1.5"))?;
+        assert_eq!(account.get_available_funds(), amount("-3.5"));
+        account.adjust(amount("4.0"))?;
+        assert_eq!(account.get_available_funds(), amount("0.5"));
+        assert_eq!(account.get_total_funds(), amount("0.5"));
+        Ok(())
+    }
 
-        account.dispute(4.0);
-        assert_eq!(account.get_available_funds(), 2.0);
-        assert_eq!(account.get_held_funds(), 4.0);
-        assert_eq!(account.get_total_funds(), 6.0);
+    #[test]
+    fn test_account_dispute_descreases_available_funds_and_increases_held_funds(
+    ) -> Result<(), AccountError> {
+        let mut account = Account::new();
+        account.deposit(amount("6.0"))?;
+
+        account.dispute(amount("4.0"))?;
+        assert_eq!(account.get_available_funds(), amount("2.0"));
+        assert_eq!(account.get_held_funds(), amount("4.0"));
+        assert_eq!(account.get_total_funds(), amount("6.0"));
+        Ok(())
     }
 
     #[test]
-    fn test_account_resolve_increases_available_and_held_funds() {
+    fn test_account_resolve_reverts_a_dispute() -> Result<(), AccountError> {
         let mut account = Account::new();
-        account.deposit(6.0);
+        account.deposit(amount("6.0"))?;
+
+        account.dispute(amount("4.0"))?;
 
-        account.resolve(3.0);
-        assert_eq!(account.get_available_funds(), 9.0);
-        assert_eq!(account.get_held_funds(), -3.0);
-        assert_eq!(account.get_total_funds(), 6.0);
+        account.resolve(amount("4.0"))?;
+        assert_eq!(account.get_available_funds(), amount("6.0"));
+        assert_eq!(account.get_held_funds(), Amount::ZERO);
+        assert_eq!(account.get_total_funds(), amount("6.0"));
+        Ok(())
     }
 
     #[test]
-    fn test_account_resolve_reverts_a_dispute() {
+    fn test_account_chargeback_locks_account_and_decreases_total_and_held_funds(
+    ) -> Result<(), AccountError> {
         let mut account = Account::new();
-        account.deposit(6.0);
+        account.deposit(amount("6.0"))?;
 
-        account.dispute(4.0);
+        account.dispute(amount("4.0"))?;
 
-        account.resolve(4.0);
-        assert_eq!(account.get_available_funds(), 6.0);
-        assert_eq!(account.get_held_funds(), 0.0);
-        assert_eq!(account.get_total_funds(), 6.0);
+        account.chargeback(amount("4.0"))?;
+        assert!(account.is_locked());
+        assert_eq!(account.get_available_funds(), amount("2.0"));
+        assert_eq!(account.get_held_funds(), Amount::ZERO);
+        assert_eq!(account.get_total_funds(), amount("2.0"));
+        Ok(())
     }
 
     #[test]
-    fn test_account_chargeback_locks_account_and_decreases_total_and_held_funds() {
+    fn test_account_chargeback_of_a_reserved_withdrawal_credits_it_back() -> Result<(), AccountError>
+    {
         let mut account = Account::new();
-        account.deposit(6.0);
+        account.deposit(amount("10.0"))?;
+        account.withdrawal(amount("4.0"))?;
 
-        account.dispute(4.0);
+        account.dispute(amount("4.0"))?;
 
-        account.chargeback(4.0);
-        assert_eq!(account.is_locked(), true);
-        assert_eq!(account.get_available_funds(), 2.0);
-        assert_eq!(account.get_held_funds(), 0.0);
-        assert_eq!(account.get_total_funds(), 2.0);
+        account.chargeback_reserved_withdrawal(amount("4.0"))?;
+        assert!(account.is_locked());
+        assert_eq!(account.get_available_funds(), amount("10.0"));
+        assert_eq!(account.get_held_funds(), Amount::ZERO);
+        assert_eq!(account.get_total_funds(), amount("10.0"));
+        Ok(())
     }
 
     #[test]
     fn test_account_withdrawal_fails_on_insufficient_funds() {
         let mut account = Account::new();
         assert!(matches!(
-            account.withdrawal(1.0).unwrap_err(),
+            account.withdrawal(amount("1.0")).unwrap_err(),
             AccountError::InsufficientFunds(_)
         ));
     }
 
     #[test]
-    fn test_precision_of_funds_are_4_digits_past_the_decimal_with_closest_integer_rounding() {
+    fn test_precision_of_funds_are_4_digits_past_the_decimal_with_closest_integer_rounding(
+    ) -> Result<(), AccountError> {
+        let mut account = Account::new();
+        account.deposit(amount("4.0001"))?;
+        account.deposit(amount("4.00005"))?;
+
+        assert_eq!(account.get_available_funds(), amount("8.0002"));
+        Ok(())
+    }
+
+    /// Every record is rounded on its own, see the rounding rule under "Input" in the README
+    #[test]
+    fn test_two_values_half_the_precision_results_in_the_smallest_precision_number(
+    ) -> Result<(), AccountError> {
+        let mut account = Account::new();
+        account.deposit(amount("0.00005"))?;
+        account.deposit(amount("0.00005"))?;
+
+        assert_eq!(account.get_available_funds(), amount("0.0002"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_small_deposits_do_not_drift() -> Result<(), AccountError> {
+        let mut account = Account::new();
+        for _ in 0..10_000 {
+            account.deposit(amount("0.1"))?;
+        }
+
+        assert_eq!(account.get_available_funds(), amount("1000"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_deposit_fails_on_overflow_and_keeps_funds() -> Result<(), AccountError> {
         let mut account = Account::new();
-        account.deposit(4.0001);
-        account.deposit(4.00005);
+        account.deposit(Amount::MAX)?;
 
-        assert_eq!(account.get_available_funds(), 8.0002);
+        assert!(matches!(
+            account.deposit(amount("0.0001")).unwrap_err(),
+            AccountError::Overflow(_)
+        ));
+        assert_eq!(account.get_available_funds(), Amount::MAX);
+        Ok(())
     }
 
     #[test]
-    fn test_two_values_half_the_precision_results_in_the_smallest_precision_number() {
+    fn test_dispute_fails_when_total_funds_would_overflow() -> Result<(), AccountError> {
         let mut account = Account::new();
-        account.deposit(0.00005);
-        account.deposit(0.00005);
+        account.deposit(Amount::MAX)?;
+        account.dispute(Amount::MAX)?;
 
-        assert_eq!(account.get_available_funds(), 0.0001);
+        assert!(matches!(
+            account.deposit(amount("1.0")).unwrap_err(),
+            AccountError::Overflow(_)
+        ));
+        assert_eq!(account.get_total_funds(), Amount::MAX);
+        Ok(())
     }
 }
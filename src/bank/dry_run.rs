@@ -0,0 +1,213 @@
+use super::{
+    client::Account,
+    store::{AccountStore, StoreError, TxStore},
+    AccountReport, Bank, BankError, ClientId, DisputableTx, TxError, TxOutcome,
+};
+use crate::transactions::{Transaction, TxId};
+use error_stack::{Result, ResultExt};
+use std::collections::HashMap;
+
+/// Store which reads through to a base store and keeps every write to itself
+struct Overlay<'a, S, K, V> {
+    base: &'a S,
+    changes: HashMap<K, V>,
+}
+
+impl<'a, S, K, V> Overlay<'a, S, K, V> {
+    fn new(base: &'a S) -> Overlay<'a, S, K, V> {
+        Overlay {
+            base,
+            changes: HashMap::new(),
+        }
+    }
+}
+
+impl<A: AccountStore> AccountStore for Overlay<'_, A, ClientId, Account> {
+    fn account(&self, client: ClientId) -> Result<Option<Account>, StoreError> {
+        match self.changes.get(&client) {
+            Some(account) => Ok(Some(account.clone())),
+            None => self.base.account(client),
+        }
+    }
+
+    fn store_account(&mut self, client: ClientId, account: Account) -> Result<(), StoreError> {
+        self.changes.insert(client, account);
+        Ok(())
+    }
+
+    fn accounts(&self) -> Result<Vec<(ClientId, Account)>, StoreError> {
+        let mut accounts: Vec<(ClientId, Account)> = self
+            .base
+            .accounts()?
+            .into_iter()
+            .filter(|(client, _)| !self.changes.contains_key(client))
+            .collect();
+        accounts.extend(
+            self.changes
+                .iter()
+                .map(|(client, account)| (*client, account.clone())),
+        );
+        Ok(accounts)
+    }
+}
+
+impl<T: TxStore> TxStore for Overlay<'_, T, TxId, DisputableTx> {
+    fn disputable_tx(&self, tx: TxId) -> Result<Option<DisputableTx>, StoreError> {
+        match self.changes.get(&tx) {
+            Some(disputable_tx) => Ok(Some(disputable_tx.clone())),
+            None => self.base.disputable_tx(tx),
+        }
+    }
+
+    fn store_disputable_tx(
+        &mut self,
+        tx: TxId,
+        disputable_tx: DisputableTx,
+    ) -> Result<(), StoreError> {
+        self.changes.insert(tx, disputable_tx);
+        Ok(())
+    }
+}
+
+/// Account which a dry run would change, `before` is empty for a new account
+#[derive(Debug, PartialEq)]
+pub struct AccountChange {
+    pub before: Option<AccountReport>,
+    pub after: AccountReport,
+}
+
+/// Transactions applied on top of a bank without changing the bank itself
+///
+/// The same rules apply as for [`Bank::apply`], but every change is kept aside and dropped
+/// together with the dry run. Only the used transaction ids are copied, the stores of the bank
+/// are read through.
+pub struct DryRun<'a, A, T> {
+    bank: Bank<Overlay<'a, A, ClientId, Account>, Overlay<'a, T, TxId, DisputableTx>>,
+}
+
+impl<A: AccountStore, T: TxStore> Bank<A, T> {
+    pub fn dry_run(&self) -> DryRun<'_, A, T> {
+        DryRun {
+            bank: Bank {
+                clients: Overlay::new(&self.clients),
+                disputable_txs: Overlay::new(&self.disputable_txs),
+                seen_tx_ids: self.seen_tx_ids.clone(),
+                dispute_policy: self.dispute_policy,
+                locked_account_policy: self.locked_account_policy,
+                fee_policy: self.fee_policy,
+                journal: None,
+                history: None,
+                audit_trail: Vec::new(),
+            },
+        }
+    }
+}
+
+impl<A: AccountStore, T: TxStore> DryRun<'_, A, T> {
+    pub fn apply(&mut self, tx: &Transaction) -> Result<(), TxError> {
+        self.bank.apply(tx)
+    }
+
+    pub fn handle_transactions<I>(&mut self, transactions: I) -> Vec<TxOutcome>
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        self.bank.handle_transactions(transactions)
+    }
+
+    /// Accounts which would differ from the bank, sorted by client
+    pub fn account_changes(&self) -> Result<Vec<AccountChange>, BankError> {
+        let base = self.bank.clients.base;
+        let mut changes = Vec::new();
+        for (client, account) in &self.bank.clients.changes {
+            let before = base
+                .account(*client)
+                .change_context(BankError::Other)?
+                .map(|account| AccountReport::from_account(*client, &account));
+            let after = AccountReport::from_account(*client, account);
+            if before.as_ref() != Some(&after) {
+                changes.push(AccountChange { before, after });
+            }
+        }
+        changes.sort_by_key(|change| change.after.client);
+        Ok(changes)
+    }
+
+    /// Report of all accounts as they would be after the dry run
+    pub fn accounts_report(&self) -> Result<Vec<AccountReport>, BankError> {
+        self.bank.try_get_accounts_report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::transactions::Kind;
+
+    #[test]
+    fn test_dry_run_leaves_the_bank_untouched() {
+        let mut bank = Bank::new();
+        bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+            Transaction::new(Kind::Deposit, 2, 2, Some(amount("1.0"))),
+        ]);
+        let report = bank.get_accounts_report();
+
+        let mut dry_run = bank.dry_run();
+        assert_eq!(
+            dry_run.handle_transactions(vec![
+                Transaction::new(Kind::Withdrawal, 1, 3, Some(amount("2.0"))),
+                Transaction::new(Kind::Deposit, 3, 1, Some(amount("1.0"))),
+                Transaction::new(Kind::Withdrawal, 2, 4, Some(amount("3.0"))),
+                Transaction::new(Kind::Deposit, 1, 5, Some(amount("4.0"))),
+                Transaction::new(Kind::Dispute, 1, 5, None),
+            ]),
+            vec![
+                TxOutcome::Accepted,
+                TxOutcome::Rejected(TxError::DuplicateTx),
+                TxOutcome::Rejected(TxError::InsufficientFunds),
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+            ]
+        );
+        assert_eq!(
+            dry_run
+                .account_changes()
+                .expect("In-memory stores cannot fail"),
+            vec![
+                AccountChange {
+                    before: Some(AccountReport::new(
+                        1,
+                        amount("5.0"),
+                        amount("0"),
+                        amount("5.0"),
+                        false
+                    )),
+                    after: AccountReport::new(
+                        1,
+                        amount("3.0"),
+                        amount("4.0"),
+                        amount("7.0"),
+                        false
+                    ),
+                },
+                AccountChange {
+                    before: None,
+                    after: AccountReport::new(3, amount("0"), amount("0"), amount("0"), false),
+                },
+            ]
+        );
+
+        assert_eq!(bank.get_accounts_report(), report);
+        // the withdrawal id is still free in the bank itself
+        assert!(bank
+            .apply(&Transaction::new(
+                Kind::Withdrawal,
+                1,
+                3,
+                Some(amount("2.0"))
+            ))
+            .is_ok());
+    }
+}
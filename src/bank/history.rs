@@ -0,0 +1,165 @@
+use super::{
+    client::{Account, Amount},
+    store::{AccountStore, TxStore},
+    Bank, ClientId,
+};
+use crate::transactions::{Kind, Transaction, TxId};
+use serde::Serialize;
+use std::{collections::HashMap, ops::RangeBounds};
+
+/// Balances of an account right after one of its transactions was applied
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    /// Position of the transaction among all transactions applied to the bank since the history
+    /// was enabled, starting at 1
+    pub position: u64,
+    pub tx: TxId,
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    pub amount: Option<Amount>,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+}
+
+/// Accepted transactions of every client in the order they were applied
+///
+/// The history is only kept in memory, it is not part of a snapshot.
+#[derive(Default)]
+pub struct History {
+    position: u64,
+    entries: HashMap<ClientId, Vec<HistoryEntry>>,
+}
+
+impl History {
+    /// Count a transaction which is about to be applied, whether it is accepted or not
+    pub(super) fn next_position(&mut self) {
+        self.position += 1;
+    }
+
+    /// Keep an accepted transaction with the new state of its account
+    pub(super) fn record(&mut self, tx: &Transaction, account: &Account) {
+        self.entries
+            .entry(tx.client)
+            .or_default()
+            .push(HistoryEntry {
+                position: self.position,
+                tx: tx.tx,
+                kind: tx.kind,
+                amount: tx.amount,
+                available: account.get_available_funds(),
+                held: account.get_held_funds(),
+                total: account.get_total_funds(),
+            });
+    }
+}
+
+impl<A: AccountStore, T: TxStore> Bank<A, T> {
+    /// Keep the history of every account from now on
+    pub fn with_history(mut self) -> Bank<A, T> {
+        self.history = Some(History::default());
+        self
+    }
+
+    /// Accepted transactions of a client with the balances after each of them, empty when the
+    /// history is not kept
+    pub fn history(&self, client: ClientId) -> &[HistoryEntry] {
+        self.history
+            .as_ref()
+            .and_then(|history| history.entries.get(&client))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Movements of a client between two positions in the stream of applied transactions
+    pub fn statement(
+        &self,
+        client: ClientId,
+        positions: impl RangeBounds<u64>,
+    ) -> Vec<HistoryEntry> {
+        self.history(client)
+            .iter()
+            .filter(|entry| positions.contains(&entry.position))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::bank::store::{MemoryAccountStore, MemoryTxStore, ReadOnlyAccounts};
+    use crate::bank::TxError;
+
+    #[test]
+    fn test_statement_contains_running_balances_between_positions() {
+        let mut bank = Bank::new().with_history();
+        bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+            Transaction::new(Kind::Deposit, 2, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 3, Some(amount("9.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 4, Some(amount("2.0"))),
+            Transaction::new(Kind::Dispute, 1, 1, None),
+        ]);
+
+        let entry = |position, tx, kind, amount, available, held, total| HistoryEntry {
+            position,
+            tx,
+            kind,
+            amount,
+            available,
+            held,
+            total,
+        };
+        // the rejected withdrawal still takes up a position
+        assert_eq!(
+            bank.history(1),
+            &[
+                entry(
+                    1,
+                    1,
+                    Kind::Deposit,
+                    Some(amount("5.0")),
+                    amount("5.0"),
+                    amount("0"),
+                    amount("5.0")
+                ),
+                entry(
+                    4,
+                    4,
+                    Kind::Withdrawal,
+                    Some(amount("2.0")),
+                    amount("3.0"),
+                    amount("0"),
+                    amount("3.0")
+                ),
+                entry(
+                    5,
+                    1,
+                    Kind::Dispute,
+                    None,
+                    amount("-2.0"),
+                    amount("5.0"),
+                    amount("3.0")
+                ),
+            ]
+        );
+        assert_eq!(bank.statement(1, 2..=4), bank.history(1)[1..2]);
+        assert!(Bank::new().history(1).is_empty());
+    }
+
+    #[test]
+    fn test_a_change_which_cannot_be_stored_is_not_in_the_history() {
+        let mut bank = Bank::with_stores(
+            ReadOnlyAccounts(MemoryAccountStore::new()),
+            MemoryTxStore::new(),
+        )
+        .with_history();
+
+        let err = bank
+            .apply(&Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))))
+            .unwrap_err();
+        assert_eq!(*err.current_context(), TxError::Storage);
+        assert!(bank.history(1).is_empty());
+    }
+}
@@ -0,0 +1,711 @@
+use super::{
+    audit::AdminError,
+    store::{AccountStore, TxStore},
+    Bank, ClientId, DisputePolicy, FeePolicy, LockedAccountPolicy, TxError,
+};
+use crate::transactions::Transaction;
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"TXJOURNL";
+
+/// Version of the journal layout, increase it whenever the journaled entries change
+pub const JOURNAL_VERSION: u32 = 4;
+
+/// Number of journaled transactions after which the journal is synced to disk by default
+pub const DEFAULT_SYNC_EVERY: usize = 1024;
+
+const HEADER_LEN: u64 = MAGIC.len() as u64 + 4;
+
+#[derive(Debug, PartialEq)]
+pub enum JournalError {
+    InvalidJournal(String),
+    Other,
+}
+
+impl Context for JournalError {}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Bank journal failed")
+    }
+}
+
+/// Policies of a [`Bank`] which change the outcome of a transaction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(super) struct Policies {
+    dispute: DisputePolicy,
+    locked_account: LockedAccountPolicy,
+    fee: FeePolicy,
+}
+
+/// A journaled change of the state of a [`Bank`]
+#[derive(Debug, Serialize, Deserialize)]
+enum Entry {
+    /// A transaction together with its position in the stream of applied transactions
+    Tx { position: u64, tx: Transaction },
+    /// An account unlocked by an operator, which does not take a position of the input
+    Unlock {
+        client: ClientId,
+        operator: String,
+        reason: String,
+    },
+    /// The run which journaled the entries before ended successfully, the positions of the next
+    /// run start from zero again
+    Completed,
+    /// Policies the following transactions are applied with, the default ones until the first
+    Policies(Policies),
+}
+
+/// Append-only log of the transactions which changed the state of a [`Bank`]
+///
+/// Every entry is written as its length, a CRC32 of its content and the content itself. The file
+/// is only synced to disk once every `sync_every` entries, a crash loses at most the entries
+/// since the last sync, together with the state they changed.
+pub struct Journal {
+    writer: BufWriter<File>,
+    sync_every: usize,
+    unsynced: usize,
+    position: u64,
+    /// Policies of the last journaled transactions
+    policies: Option<Policies>,
+}
+
+impl Journal {
+    /// Start an empty journal, an existing file is truncated
+    pub fn create(journal_abs_path: &Path, sync_every: usize) -> Result<Journal, JournalError> {
+        let file = File::create(journal_abs_path)
+            .report()
+            .change_context(JournalError::Other)
+            .attach_printable(format!("{journal_abs_path:?} cannot be created"))?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(MAGIC)
+            .and_then(|_| writer.write_all(&JOURNAL_VERSION.to_le_bytes()))
+            .report()
+            .change_context(JournalError::Other)
+            .attach_printable("Failed to write the journal header")?;
+        let mut journal = Journal::new(writer, sync_every, 0, None);
+        journal.sync()?;
+        Ok(journal)
+    }
+
+    fn new(
+        writer: BufWriter<File>,
+        sync_every: usize,
+        position: u64,
+        policies: Option<Policies>,
+    ) -> Journal {
+        Journal {
+            writer,
+            sync_every: sync_every.max(1),
+            unsynced: 0,
+            position,
+            policies,
+        }
+    }
+
+    /// Number of transactions applied since the journal was started
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Count a transaction, it is only written when it changes the state of the bank, after the
+    /// policies it was applied with when they differ from the last journaled ones
+    pub(super) fn record(
+        &mut self,
+        tx: &Transaction,
+        policies: Policies,
+        changed: bool,
+    ) -> Result<(), JournalError> {
+        if changed {
+            if self.policies != Some(policies) {
+                self.append(&Entry::Policies(policies))?;
+                self.policies = Some(policies);
+            }
+            self.append(&Entry::Tx {
+                position: self.position,
+                tx: tx.clone(),
+            })?;
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    pub(super) fn record_unlock(
+        &mut self,
+        client: ClientId,
+        operator: &str,
+        reason: &str,
+    ) -> Result<(), JournalError> {
+        self.append(&Entry::Unlock {
+            client,
+            operator: operator.to_owned(),
+            reason: reason.to_owned(),
+        })
+    }
+
+    /// Mark the run as finished, a later recovery replays its entries without resuming any of its
+    /// transactions
+    fn complete(&mut self) -> Result<(), JournalError> {
+        self.append(&Entry::Completed)?;
+        self.sync()?;
+        self.position = 0;
+        Ok(())
+    }
+
+    fn append(&mut self, entry: &Entry) -> Result<(), JournalError> {
+        let content = bincode::serialize(entry)
+            .report()
+            .change_context(JournalError::Other)
+            .attach_printable(format!("Failed to serialize {entry:?}"))?;
+        let len = u32::try_from(content.len())
+            .report()
+            .change_context(JournalError::Other)?;
+        self.writer
+            .write_all(&len.to_le_bytes())
+            .and_then(|_| {
+                self.writer
+                    .write_all(&crc32fast::hash(&content).to_le_bytes())
+            })
+            .and_then(|_| self.writer.write_all(&content))
+            .report()
+            .change_context(JournalError::Other)
+            .attach_printable(format!("Failed to journal {entry:?}"))?;
+
+        self.unsynced += 1;
+        if self.unsynced >= self.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Flush all journaled entries and wait until they are on disk
+    pub fn sync(&mut self) -> Result<(), JournalError> {
+        self.writer
+            .flush()
+            .and_then(|_| self.writer.get_ref().sync_data())
+            .report()
+            .change_context(JournalError::Other)
+            .attach_printable("Failed to sync the journal")?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+/// Read the next entry, `None` at the end of the journal or at an entry which was only partly
+/// written
+fn read_entry<R: Read>(reader: &mut R) -> Result<Option<(Entry, u64)>, JournalError> {
+    let mut header = [0; 8];
+    let mut content = Vec::new();
+    let complete = read_complete(reader, &mut header)
+        .and_then(|complete| {
+            if !complete {
+                return Ok(false);
+            }
+            let len = u32::from_le_bytes(header[..4].try_into().expect("4 bytes"));
+            content.resize(len as usize, 0);
+            read_complete(reader, &mut content)
+        })
+        .report()
+        .change_context(JournalError::Other)
+        .attach_printable("Failed to read the journal")?;
+    if !complete {
+        return Ok(None);
+    }
+    let checksum = u32::from_le_bytes(header[4..].try_into().expect("4 bytes"));
+    if crc32fast::hash(&content) != checksum {
+        log::warn!("Journal entry has an invalid checksum, dropping the rest of the journal");
+        return Ok(None);
+    }
+
+    let entry =
+        bincode::deserialize(&content)
+            .report()
+            .change_context(JournalError::InvalidJournal(
+                "journal entry cannot be read".to_owned(),
+            ))?;
+    Ok(Some((entry, header.len() as u64 + content.len() as u64)))
+}
+
+/// Fill the whole buffer, returns false when the input ends before
+fn read_complete<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+impl<A: AccountStore, T: TxStore> Bank<A, T> {
+    pub fn with_journal(mut self, journal: Journal) -> Bank<A, T> {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Replay a journal on top of the current state and keep journaling to the same file
+    ///
+    /// The state must be the one the journal was started from, usually the last snapshot. An
+    /// entry which was only partly written when the process died is cut off. Returns the number
+    /// of transactions which were already applied by an unfinished run, processing continues
+    /// with the next one. Runs marked as complete only add their state. An unfinished run can
+    /// only be resumed with the policies it was started with.
+    pub fn recover_journal(
+        &mut self,
+        journal_abs_path: &Path,
+        sync_every: usize,
+    ) -> Result<u64, JournalError> {
+        if !journal_abs_path.exists() {
+            self.journal = Some(Journal::create(journal_abs_path, sync_every)?);
+            return Ok(0);
+        }
+
+        let file = File::open(journal_abs_path)
+            .report()
+            .change_context(JournalError::Other)
+            .attach_printable(format!("{journal_abs_path:?} cannot be opened"))?;
+        let mut reader = BufReader::new(file);
+        let mut header = [0; HEADER_LEN as usize];
+        reader
+            .read_exact(&mut header)
+            .report()
+            .change_context(JournalError::InvalidJournal(
+                "journal header cannot be read".to_owned(),
+            ))?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(Report::new(JournalError::InvalidJournal(
+                "not a bank journal".to_owned(),
+            )));
+        }
+        let version = u32::from_le_bytes(header[MAGIC.len()..].try_into().expect("4 bytes"));
+        if version != JOURNAL_VERSION {
+            return Err(Report::new(JournalError::InvalidJournal(format!(
+                "unsupported journal version {version}"
+            ))));
+        }
+
+        self.journal = None;
+        let policies = self.policies();
+        let mut journaled = Policies::default();
+        let (mut valid_len, mut position) = (HEADER_LEN, 0);
+        while let Some((entry, len)) = read_entry(&mut reader)? {
+            match entry {
+                Entry::Tx {
+                    position: tx_position,
+                    tx,
+                } => {
+                    // the outcome is the same as when the entry was journaled, only a failing
+                    // store makes it differ
+                    match self.apply(&tx) {
+                        Err(err)
+                            if matches!(
+                                err.current_context(),
+                                TxError::Storage | TxError::Journal
+                            ) =>
+                        {
+                            return Err(err
+                                .change_context(JournalError::Other)
+                                .attach_printable(format!("Failed to replay {tx:?}")))
+                        }
+                        _ => position = tx_position + 1,
+                    }
+                }
+                Entry::Unlock {
+                    client,
+                    operator,
+                    reason,
+                } => match self.unlock_account(client, &operator, &reason) {
+                    Err(err)
+                        if matches!(
+                            err.current_context(),
+                            AdminError::Storage | AdminError::Journal
+                        ) =>
+                    {
+                        return Err(err
+                            .change_context(JournalError::Other)
+                            .attach_printable(format!("Failed to replay the unlock of {client}")))
+                    }
+                    _ => (),
+                },
+                // the state of a finished run stays, but none of its input is resumed
+                Entry::Completed => position = 0,
+                Entry::Policies(entry_policies) => {
+                    journaled = entry_policies;
+                    self.set_policies(journaled);
+                }
+            }
+            valid_len += len;
+        }
+        self.set_policies(policies);
+        if position > 0 && journaled != policies {
+            return Err(Report::new(JournalError::InvalidJournal(format!(
+                "the interrupted run used other policies, {journaled:?}"
+            ))));
+        }
+        log::info!("Replayed {journal_abs_path:?}, resuming after {position} transactions");
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(journal_abs_path)
+            .and_then(|file| {
+                file.set_len(valid_len)?;
+                Ok(file)
+            })
+            .report()
+            .change_context(JournalError::Other)
+            .attach_printable(format!("{journal_abs_path:?} cannot be appended to"))?;
+        file.seek(SeekFrom::End(0))
+            .report()
+            .change_context(JournalError::Other)?;
+        self.journal = Some(Journal::new(
+            BufWriter::new(file),
+            sync_every,
+            position,
+            Some(journaled),
+        ));
+        Ok(position)
+    }
+
+    pub(super) fn policies(&self) -> Policies {
+        Policies {
+            dispute: self.dispute_policy,
+            locked_account: self.locked_account_policy,
+            fee: self.fee_policy,
+        }
+    }
+
+    fn set_policies(&mut self, policies: Policies) {
+        self.dispute_policy = policies.dispute;
+        self.locked_account_policy = policies.locked_account;
+        self.fee_policy = policies.fee;
+    }
+
+    /// Make sure every journaled transaction is on disk
+    pub fn sync_journal(&mut self) -> Result<(), JournalError> {
+        match &mut self.journal {
+            Some(journal) => journal.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Mark the journaled run as finished, so a later run with the same journal continues from
+    /// its state instead of skipping the transactions it already applied
+    pub fn complete_journal(&mut self) -> Result<(), JournalError> {
+        match &mut self.journal {
+            Some(journal) => journal.complete(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::bank::store::{MemoryAccountStore, MemoryTxStore, ReadOnlyAccounts};
+    use crate::bank::TxOutcome;
+    use crate::transactions::Kind;
+
+    fn transactions() -> Vec<Transaction> {
+        vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("3.0"))),
+            Transaction::new(Kind::Withdrawal, 2, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 1, None),
+            Transaction::new(Kind::Resolve, 1, 7, None),
+        ]
+    }
+
+    #[test]
+    fn test_a_replayed_journal_restores_the_state_and_position() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+
+        let mut bank = Bank::new();
+        assert_eq!(
+            bank.recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            0
+        );
+        bank.handle_transactions(transactions());
+        drop(bank);
+
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            3
+        );
+
+        let mut expected = Bank::new();
+        expected.handle_transactions(transactions());
+        assert_eq!(
+            recovered.get_accounts_report(),
+            expected.get_accounts_report()
+        );
+        // the id of the rejected withdrawal is still in use
+        assert_eq!(
+            recovered.handle_transactions(vec![Transaction::new(
+                Kind::Deposit,
+                2,
+                2,
+                Some(amount("1.0"))
+            )]),
+            vec![TxOutcome::Rejected(TxError::DuplicateTx)]
+        );
+    }
+
+    #[test]
+    fn test_a_completed_run_is_replayed_without_being_resumed() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+
+        let mut bank = Bank::new();
+        bank.recover_journal(&journal_file, 1)
+            .expect("Creating journal failed");
+        bank.handle_transactions(transactions());
+        bank.complete_journal().expect("Completing journal failed");
+        drop(bank);
+
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            0
+        );
+        let mut expected = Bank::new();
+        expected.handle_transactions(transactions());
+        assert_eq!(
+            recovered.get_accounts_report(),
+            expected.get_accounts_report()
+        );
+
+        // the next run is resumed from its own first transaction
+        recovered.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 7, 8, Some(amount("2.0"))),
+            Transaction::new(Kind::Deposit, 7, 9, Some(amount("1.0"))),
+        ]);
+        drop(recovered);
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            2
+        );
+        assert_eq!(recovered.clients[&7].get_available_funds(), amount("3.0"));
+    }
+
+    #[test]
+    fn test_an_unlock_is_replayed_at_the_same_point() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+
+        let mut bank = Bank::new();
+        bank.recover_journal(&journal_file, 1)
+            .expect("Creating journal failed");
+        bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+            Transaction::new(Kind::Deposit, 1, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 2, None),
+            Transaction::new(Kind::Chargeback, 1, 2, None),
+        ]);
+        bank.unlock_account(1, "ops-7", "customer verified")
+            .expect("Unlocking failed");
+        bank.handle_transactions(vec![Transaction::new(
+            Kind::Withdrawal,
+            1,
+            3,
+            Some(amount("2.0")),
+        )]);
+        let report = bank.get_accounts_report();
+        let audit_trail = bank.audit_trail().to_vec();
+        drop(bank);
+
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            5
+        );
+        assert_eq!(recovered.get_accounts_report(), report);
+        assert_eq!(recovered.audit_trail(), audit_trail);
+    }
+
+    #[test]
+    fn test_a_run_is_replayed_and_resumed_with_its_policies() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+        let locked_transactions = vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+            Transaction::new(Kind::Deposit, 1, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 1, 2, None),
+            Transaction::new(Kind::Chargeback, 1, 2, None),
+            Transaction::new(Kind::Deposit, 1, 3, Some(amount("2.0"))),
+        ];
+
+        let mut bank = Bank::new().with_locked_account_policy(LockedAccountPolicy::DepositsOnly);
+        bank.recover_journal(&journal_file, 1)
+            .expect("Creating journal failed");
+        bank.handle_transactions(locked_transactions.clone());
+        drop(bank);
+
+        assert_eq!(
+            *Bank::new()
+                .recover_journal(&journal_file, 1)
+                .unwrap_err()
+                .current_context(),
+            JournalError::InvalidJournal(
+                "the interrupted run used other policies, Policies { dispute: CreditBack, \
+                 locked_account: DepositsOnly, fee: RequireFunds }"
+                    .to_owned()
+            )
+        );
+
+        let mut recovered =
+            Bank::new().with_locked_account_policy(LockedAccountPolicy::DepositsOnly);
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            5
+        );
+        assert_eq!(recovered.clients[&1].get_available_funds(), amount("7.0"));
+        recovered
+            .complete_journal()
+            .expect("Completing journal failed");
+        drop(recovered);
+
+        // a completed run is replayed with its own policies whatever the next run uses
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            0
+        );
+        assert_eq!(recovered.clients[&1].get_available_funds(), amount("7.0"));
+        assert_eq!(
+            recovered.handle_transactions(vec![Transaction::new(
+                Kind::Deposit,
+                1,
+                4,
+                Some(amount("1.0"))
+            )]),
+            vec![TxOutcome::Rejected(TxError::AccountLocked)]
+        );
+    }
+
+    #[test]
+    fn test_a_partly_written_entry_is_cut_off() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+
+        let mut bank = Bank::new();
+        bank.recover_journal(&journal_file, 1)
+            .expect("Creating journal failed");
+        bank.handle_transactions(transactions());
+        drop(bank);
+        let len = std::fs::metadata(&journal_file)
+            .expect("Journal exists")
+            .len();
+        File::options()
+            .write(true)
+            .open(&journal_file)
+            .and_then(|file| file.set_len(len - 3))
+            .expect("Truncating journal failed");
+
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            2
+        );
+        assert_eq!(recovered.clients[&1].get_available_funds(), amount("3.0"));
+        assert_eq!(recovered.clients[&1].get_held_funds(), amount("0.0"));
+
+        // the journal continues right after the last complete entry
+        recovered.handle_transactions(vec![Transaction::new(Kind::Dispute, 1, 1, None)]);
+        drop(recovered);
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            3
+        );
+        assert_eq!(recovered.clients[&1].get_held_funds(), amount("3.0"));
+    }
+
+    #[test]
+    fn test_an_entry_with_an_invalid_checksum_ends_the_replay() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+
+        let mut bank = Bank::new();
+        bank.recover_journal(&journal_file, 1)
+            .expect("Creating journal failed");
+        bank.handle_transactions(transactions());
+        drop(bank);
+        let mut content = std::fs::read(&journal_file).expect("Reading journal failed");
+        let last = content.len() - 1;
+        content[last] ^= 0xff;
+        std::fs::write(&journal_file, content).expect("Writing journal failed");
+
+        let mut recovered = Bank::new();
+        assert_eq!(
+            recovered
+                .recover_journal(&journal_file, 1)
+                .expect("Recovering journal failed"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_a_failing_store_fails_the_replay() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+
+        let mut bank = Bank::new();
+        bank.recover_journal(&journal_file, 1)
+            .expect("Creating journal failed");
+        bank.handle_transactions(transactions());
+        drop(bank);
+
+        let mut recovered = Bank::with_stores(
+            ReadOnlyAccounts(MemoryAccountStore::new()),
+            MemoryTxStore::new(),
+        );
+        assert_eq!(
+            *recovered
+                .recover_journal(&journal_file, 1)
+                .unwrap_err()
+                .current_context(),
+            JournalError::Other
+        );
+    }
+
+    #[test]
+    fn test_other_files_are_refused_as_journal() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+        std::fs::write(&journal_file, "type,client,tx,amount\n").expect("Writing file failed");
+
+        assert_eq!(
+            *Bank::new()
+                .recover_journal(&journal_file, 1)
+                .unwrap_err()
+                .current_context(),
+            JournalError::InvalidJournal("not a bank journal".to_owned())
+        );
+    }
+}
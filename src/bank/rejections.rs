@@ -0,0 +1,111 @@
+use super::{BankError, ClientId, TxError};
+use crate::amount::Amount;
+use crate::transactions::{Kind, ParseTxError, RowError, Transaction, TxId};
+use error_stack::{IntoReport, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+const HEADER: [&str; 7] = ["input", "line", "type", "client", "tx", "amount", "reason"];
+
+/// A rejected transaction together with the input and the line it was read from
+///
+/// Records which could not be parsed at all only contain the input, the line and the reason.
+/// Std in is written as `-`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RejectedTx {
+    input: String,
+    line: u64,
+    #[serde(rename = "type")]
+    kind: Option<Kind>,
+    client: Option<ClientId>,
+    tx: Option<TxId>,
+    amount: Option<Amount>,
+    reason: String,
+}
+
+impl RejectedTx {
+    pub fn new(input: &Path, line: u64, tx: &Transaction, reason: &TxError) -> RejectedTx {
+        RejectedTx {
+            input: input.display().to_string(),
+            line,
+            kind: Some(tx.kind),
+            client: Some(tx.client),
+            tx: Some(tx.tx),
+            amount: tx.amount,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn malformed(input: &Path, row_error: &RowError) -> RejectedTx {
+        let reason = match &row_error.error {
+            ParseTxError::InvalidInput(msg) => msg.clone(),
+            err => err.to_string(),
+        };
+        RejectedTx {
+            input: input.display().to_string(),
+            line: row_error.line,
+            kind: None,
+            client: None,
+            tx: None,
+            amount: None,
+            reason,
+        }
+    }
+
+    /// Whether the record was read from the given line of an input
+    pub fn is_from(&self, input: &Path, line: u64) -> bool {
+        self.line == line && self.input == input.display().to_string()
+    }
+}
+
+/// Read back the rejected transactions written by a [`RejectionWriter`]
+pub fn read_rejections<R: Read>(reader: R) -> impl Iterator<Item = Result<RejectedTx, BankError>> {
+    csv::Reader::from_reader(reader)
+        .into_deserialize()
+        .map(|row| {
+            row.report()
+                .change_context(BankError::Other)
+                .attach_printable("Failed to read a rejected transaction")
+        })
+}
+
+/// Write rejected transactions as CSV, the header row is always written
+pub struct RejectionWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> RejectionWriter<W> {
+    pub fn new(writer: W) -> Result<RejectionWriter<W>, BankError> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b',')
+            .has_headers(false)
+            .from_writer(writer);
+
+        writer
+            .write_record(HEADER)
+            .report()
+            .change_context(BankError::Other)
+            .attach_printable("Failed to write the header of the rejected transactions")?;
+        Ok(RejectionWriter { writer })
+    }
+
+    pub fn write(&mut self, rejected: &RejectedTx) -> Result<(), BankError> {
+        self.writer
+            .serialize(rejected)
+            .report()
+            .change_context(BankError::Other)
+            .attach_printable(format!(
+                "Failed to serialize rejected transaction {rejected:?}"
+            ))
+    }
+
+    pub fn flush(&mut self) -> Result<(), BankError> {
+        self.writer
+            .flush()
+            .report()
+            .change_context(BankError::Other)
+    }
+}
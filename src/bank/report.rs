@@ -0,0 +1,205 @@
+use super::BankError;
+use error_stack::{IntoReport, Result, ResultExt};
+use serde::Serialize;
+use std::{fmt, io::Write, str::FromStr};
+
+/// Format in which the accounts report is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    /// Delimiter separated values, optionally with a header row
+    Csv { delimiter: u8, header: bool },
+    /// A single JSON array of accounts
+    Json,
+    /// One JSON account object per line
+    JsonLines,
+    /// Aligned columns for reading in a terminal
+    Table,
+}
+
+impl Default for ReportFormat {
+    fn default() -> ReportFormat {
+        ReportFormat::Csv {
+            delimiter: b',',
+            header: true,
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    /// CSV is parsed with its default delimiter and header row
+    fn from_str(format: &str) -> std::result::Result<ReportFormat, String> {
+        match format {
+            "csv" => Ok(ReportFormat::default()),
+            "json" => Ok(ReportFormat::Json),
+            "jsonl" | "ndjson" => Ok(ReportFormat::JsonLines),
+            "table" => Ok(ReportFormat::Table),
+            _ => Err(format!("Unknown report format {format}")),
+        }
+    }
+}
+
+/// Write rows of a report, such as the accounts report or a statement, in any format
+///
+/// The columns are the serialized fields of the rows.
+pub fn write_report<R: Serialize + fmt::Debug, W: Write>(
+    rows: &[R],
+    format: ReportFormat,
+    mut writer: W,
+) -> Result<(), BankError> {
+    match format {
+        ReportFormat::Csv { delimiter, header } => write_csv(rows, delimiter, header, writer),
+        ReportFormat::Json => {
+            serde_json::to_writer(&mut writer, rows)
+                .report()
+                .change_context(BankError::Other)
+                .attach_printable("Failed to serialize the report")?;
+            writeln!(writer).report().change_context(BankError::Other)
+        }
+        ReportFormat::JsonLines => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, row)
+                    .report()
+                    .change_context(BankError::Other)
+                    .attach_printable(format!("Failed to serialize report row {row:?}"))?;
+                writeln!(writer).report().change_context(BankError::Other)?;
+            }
+            Ok(())
+        }
+        ReportFormat::Table => write_table(rows, writer),
+    }
+}
+
+fn write_csv<R: Serialize + fmt::Debug, W: Write>(
+    rows: &[R],
+    delimiter: u8,
+    header: bool,
+    writer: W,
+) -> Result<(), BankError> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(header)
+        .from_writer(writer);
+
+    for row in rows {
+        writer
+            .serialize(row)
+            .report()
+            .change_context(BankError::Other)
+            .attach_printable(format!("Failed to serialize report row {row:?}"))?;
+    }
+    writer.flush().report().change_context(BankError::Other)
+}
+
+/// Header and cells of every row, as they would be written to CSV
+fn table_cells<R: Serialize + fmt::Debug>(rows: &[R]) -> Result<Vec<Vec<String>>, BankError> {
+    let mut csv = Vec::new();
+    write_csv(rows, b',', true, &mut csv)?;
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(csv.as_slice())
+        .records()
+        .map(|record| Ok(record?.iter().map(str::to_owned).collect()))
+        .collect::<std::result::Result<_, csv::Error>>()
+        .report()
+        .change_context(BankError::Other)
+}
+
+fn write_table<R: Serialize + fmt::Debug, W: Write>(
+    rows: &[R],
+    mut writer: W,
+) -> Result<(), BankError> {
+    let lines = table_cells(rows)?;
+    let Some((header, cells)) = lines.split_first() else {
+        return Ok(());
+    };
+    let columns: Vec<(usize, bool)> = (0..header.len())
+        .map(|column| {
+            let width = lines.iter().map(|line| line[column].len()).max();
+            // numbers are aligned on the right, anything else on the left
+            let numeric = cells
+                .iter()
+                .all(|line| line[column].is_empty() || line[column].parse::<f64>().is_ok());
+            (width.unwrap_or_default(), numeric)
+        })
+        .collect();
+
+    for line in &lines {
+        let line: Vec<String> = line
+            .iter()
+            .zip(&columns)
+            .map(|(cell, (width, numeric))| match numeric {
+                true => format!("{cell:>width$}"),
+                false => format!("{cell:<width$}"),
+            })
+            .collect();
+        writeln!(writer, "{}", line.join("  ").trim_end())
+            .report()
+            .change_context(BankError::Other)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::bank::AccountReport;
+
+    fn reports() -> Vec<AccountReport> {
+        vec![
+            AccountReport::new(1, amount("1.5"), amount("0"), amount("1.5"), false),
+            AccountReport::new(12, amount("-20"), amount("100"), amount("80"), true),
+        ]
+    }
+
+    fn written(format: ReportFormat) -> String {
+        let mut output = Vec::new();
+        write_report(&reports(), format, &mut output).expect("Writing report failed");
+        String::from_utf8(output).expect("UTF-8 report")
+    }
+
+    #[test]
+    fn test_csv_report_has_a_configurable_delimiter_and_header() {
+        assert_eq!(
+            written(ReportFormat::default()),
+            "client,available,held,total,locked\n\
+             1,1.5000,0.0000,1.5000,false\n\
+             12,-20.0000,100.0000,80.0000,true\n"
+        );
+        assert_eq!(
+            written(ReportFormat::Csv {
+                delimiter: b';',
+                header: false
+            }),
+            "1;1.5000;0.0000;1.5000;false\n12;-20.0000;100.0000;80.0000;true\n"
+        );
+    }
+
+    #[test]
+    fn test_json_reports_contain_one_object_per_account() {
+        let line_1 =
+            r#"{"client":1,"available":"1.5000","held":"0.0000","total":"1.5000","locked":false}"#;
+        let line_2 = r#"{"client":12,"available":"-20.0000","held":"100.0000","total":"80.0000","locked":true}"#;
+
+        assert_eq!(
+            written(ReportFormat::Json),
+            format!("[{line_1},{line_2}]\n")
+        );
+        assert_eq!(
+            written(ReportFormat::JsonLines),
+            format!("{line_1}\n{line_2}\n")
+        );
+    }
+
+    #[test]
+    fn test_table_report_aligns_the_columns() {
+        assert_eq!(
+            written(ReportFormat::Table),
+            "client  available      held    total  locked\n\
+             \x20    1     1.5000    0.0000   1.5000  false\n\
+             \x20   12   -20.0000  100.0000  80.0000  true\n"
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use super::{
+    tx_ids::TxIdSet, AccountReport, Bank, ClientId, DisputePolicy, FeePolicy, LockedAccountPolicy,
+    TxOutcome,
+};
+use crate::transactions::{has_own_id, is_disputable, Transaction, TxId};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+    thread,
+};
+
+/// Number of transactions sent to a shard at once
+const BATCH_SIZE: usize = 256;
+
+/// Number of batches which can wait for a shard before the dispatcher blocks
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Number of replies which can wait for the dispatcher before the shards block
+const REPLY_CAPACITY: usize = BATCH_SIZE * CHANNEL_CAPACITY;
+
+/// A transaction for a shard, with what the shard cannot know from its own clients
+struct Job {
+    index: usize,
+    tx: Transaction,
+    /// The id was already used by a client of another shard
+    duplicate: bool,
+    /// Owner of the referenced transaction, when it belongs to a client of another shard
+    foreign_owner: Option<ClientId>,
+}
+
+enum Message {
+    Batch(Vec<Job>),
+    /// Ask for the owner of a disputable transaction, answered once all earlier jobs are applied
+    Lookup(TxId),
+}
+
+/// What a shard sends back to the dispatcher
+enum Reply {
+    Outcome(usize, TxOutcome),
+    /// Answer to the only [`Message::Lookup`] on its way
+    Owner(Option<ClientId>),
+}
+
+/// Outcomes which arrive from the shards in any order, passed on in input order
+struct InOrder<F> {
+    next: usize,
+    pending: VecDeque<Option<TxOutcome>>,
+    on_outcome: F,
+}
+
+impl<F: FnMut(TxOutcome)> InOrder<F> {
+    fn push(&mut self, index: usize, outcome: TxOutcome) {
+        let offset = index - self.next;
+        if self.pending.len() <= offset {
+            self.pending.resize(offset + 1, None);
+        }
+        self.pending[offset] = Some(outcome);
+        while let Some(Some(_)) = self.pending.front() {
+            let outcome = self.pending.pop_front().flatten().expect("Checked above");
+            self.next += 1;
+            (self.on_outcome)(outcome);
+        }
+    }
+}
+
+/// Sending side of the dispatcher, which takes in the replies of the shards whenever it would
+/// otherwise wait for them, so neither side blocks the other
+struct Dispatcher<F> {
+    senders: Vec<SyncSender<Message>>,
+    replies: Receiver<Reply>,
+    in_order: InOrder<F>,
+}
+
+impl<F: FnMut(TxOutcome)> Dispatcher<F> {
+    fn send(&mut self, shard: usize, mut message: Message) {
+        loop {
+            match self.senders[shard].try_send(message) {
+                Ok(()) => return,
+                // the shard has work queued, so it sends outcomes while the message waits
+                Err(TrySendError::Full(full)) => {
+                    message = full;
+                    self.receive();
+                }
+                Err(TrySendError::Disconnected(_)) => panic!("Shard stopped unexpectedly"),
+            }
+        }
+    }
+
+    fn send_batch(&mut self, shard: usize, batch: &mut Vec<Job>) {
+        if !batch.is_empty() {
+            self.send(shard, Message::Batch(std::mem::take(batch)));
+        }
+    }
+
+    /// Owner of a disputable transaction, once the shard applied all earlier jobs
+    fn lookup(&mut self, shard: usize, tx: TxId) -> Option<ClientId> {
+        self.send(shard, Message::Lookup(tx));
+        loop {
+            if let Some(owner) = self.receive() {
+                return owner;
+            }
+        }
+    }
+
+    /// Wait for the next reply, outcomes are passed on and an owner is returned
+    fn receive(&mut self) -> Option<Option<ClientId>> {
+        match self.replies.recv().expect("Shards stopped unexpectedly") {
+            Reply::Outcome(index, outcome) => {
+                self.in_order.push(index, outcome);
+                None
+            }
+            Reply::Owner(owner) => Some(owner),
+        }
+    }
+
+    /// Pass on the outcomes which already arrived, without waiting
+    fn drain(&mut self) {
+        while let Ok(reply) = self.replies.try_recv() {
+            if let Reply::Outcome(index, outcome) = reply {
+                self.in_order.push(index, outcome);
+            }
+        }
+    }
+
+    /// Wait until the shards stopped and pass on all remaining outcomes
+    fn finish(mut self) {
+        self.senders.clear();
+        for reply in self.replies {
+            if let Reply::Outcome(index, outcome) = reply {
+                self.in_order.push(index, outcome);
+            }
+        }
+    }
+}
+
+/// Bank which applies the transactions of different clients on parallel worker threads
+///
+/// Every client belongs to a single shard, so the transactions of one client are still applied
+/// strictly in input order. Transaction ids are global though: a single dispatcher keeps track
+/// of the used ids and of the shard owning each disputable one, so duplicates and disputes on
+/// transactions of other clients give exactly the same outcomes as [`Bank::handle_transactions`].
+/// The ids are kept as one bit per id, like the used ids of a [`Bank`].
+pub struct ShardedBank {
+    shards: Vec<Bank>,
+    seen_tx_ids: TxIdSet,
+    /// Ids of the disputable transactions of the clients of every shard
+    owned_tx_ids: Vec<TxIdSet>,
+}
+
+impl ShardedBank {
+    pub fn new(shards: usize) -> ShardedBank {
+        let shards = shards.max(1);
+        ShardedBank {
+            shards: (0..shards).map(|_| Bank::new()).collect(),
+            seen_tx_ids: TxIdSet::new(),
+            owned_tx_ids: (0..shards).map(|_| TxIdSet::new()).collect(),
+        }
+    }
+
+    pub fn with_dispute_policy(mut self, dispute_policy: DisputePolicy) -> ShardedBank {
+        self.shards = self
+            .shards
+            .into_iter()
+            .map(|shard| shard.with_dispute_policy(dispute_policy))
+            .collect();
+        self
+    }
+
+    pub fn with_locked_account_policy(
+        mut self,
+        locked_account_policy: LockedAccountPolicy,
+    ) -> ShardedBank {
+        self.shards = self
+            .shards
+            .into_iter()
+            .map(|shard| shard.with_locked_account_policy(locked_account_policy))
+            .collect();
+        self
+    }
+
+    pub fn with_fee_policy(mut self, fee_policy: FeePolicy) -> ShardedBank {
+        self.shards = self
+            .shards
+            .into_iter()
+            .map(|shard| shard.with_fee_policy(fee_policy))
+            .collect();
+        self
+    }
+
+    /// Apply all transactions, the outcomes are returned in the same order as the input
+    pub fn handle_transactions<I>(&mut self, transactions: I) -> Vec<TxOutcome>
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        let mut outcomes = Vec::new();
+        self.handle_transactions_with(transactions, |outcome| outcomes.push(outcome));
+        outcomes
+    }
+
+    /// Apply all transactions and pass every outcome to `on_outcome` in input order, as soon as
+    /// the outcomes of all earlier transactions are known
+    ///
+    /// Only the transactions on their way through the shards are kept, so a large input can be
+    /// streamed without collecting its outcomes.
+    pub fn handle_transactions_with<I, F>(&mut self, transactions: I, on_outcome: F)
+    where
+        I: IntoIterator<Item = Transaction>,
+        F: FnMut(TxOutcome),
+    {
+        let shard_count = self.shards.len();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..shard_count)
+            .map(|_| sync_channel::<Message>(CHANNEL_CAPACITY))
+            .unzip();
+        let (reply_sender, replies) = sync_channel(REPLY_CAPACITY);
+
+        let ShardedBank {
+            shards,
+            seen_tx_ids,
+            owned_tx_ids,
+        } = self;
+        let shard_of = |client: ClientId| usize::from(client) % shard_count;
+        let mut dispatcher = Dispatcher {
+            senders,
+            replies,
+            in_order: InOrder {
+                next: 0,
+                pending: VecDeque::new(),
+                on_outcome,
+            },
+        };
+
+        thread::scope(|scope| {
+            for (bank, receiver) in shards.iter_mut().zip(receivers) {
+                let reply_sender = reply_sender.clone();
+                scope.spawn(move || run_shard(bank, receiver, reply_sender));
+            }
+            drop(reply_sender);
+
+            let mut batches: Vec<Vec<Job>> = (0..shard_count).map(|_| Vec::new()).collect();
+            for (index, tx) in transactions.into_iter().enumerate() {
+                let shard = shard_of(tx.client);
+                let mut job = Job {
+                    index,
+                    tx,
+                    duplicate: false,
+                    foreign_owner: None,
+                };
+
+                if has_own_id(&job.tx) {
+                    if !seen_tx_ids.insert(job.tx.tx) {
+                        job.duplicate = true;
+                    } else if is_disputable(&job.tx) {
+                        owned_tx_ids[shard].insert(job.tx.tx);
+                    }
+                } else if let Some(owner_shard) = (0..shard_count).find(|&owner_shard| {
+                    owner_shard != shard && owned_tx_ids[owner_shard].contains(job.tx.tx)
+                }) {
+                    // the owner's shard has to catch up before it can answer
+                    dispatcher.send_batch(owner_shard, &mut batches[owner_shard]);
+                    job.foreign_owner = dispatcher.lookup(owner_shard, job.tx.tx);
+                }
+
+                batches[shard].push(job);
+                if batches[shard].len() >= BATCH_SIZE {
+                    dispatcher.send_batch(shard, &mut batches[shard]);
+                }
+                // partly filled batches are sent as well, so no outcome is held back for long
+                if (index + 1) % (BATCH_SIZE * shard_count) == 0 {
+                    for (shard, batch) in batches.iter_mut().enumerate() {
+                        dispatcher.send_batch(shard, batch);
+                    }
+                }
+                dispatcher.drain();
+            }
+            for (shard, batch) in batches.iter_mut().enumerate() {
+                dispatcher.send_batch(shard, batch);
+            }
+            dispatcher.finish();
+        });
+    }
+
+    /// The same report as the one of a single [`Bank`] which applied all transactions
+    pub fn get_accounts_report(&self) -> Vec<AccountReport> {
+        let mut reports: Vec<AccountReport> = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.get_accounts_report())
+            .collect();
+        reports.sort_by_key(|report| report.client);
+        reports
+    }
+}
+
+fn run_shard(bank: &mut Bank, receiver: Receiver<Message>, replies: SyncSender<Reply>) {
+    for message in receiver {
+        match message {
+            Message::Batch(jobs) => {
+                for job in jobs {
+                    if job.duplicate {
+                        bank.seen_tx_ids.insert(job.tx.tx);
+                    }
+                    let outcome =
+                        TxOutcome::from(&bank.apply_referring(&job.tx, job.foreign_owner));
+                    replies
+                        .send(Reply::Outcome(job.index, outcome))
+                        .expect("Dispatcher stopped unexpectedly");
+                }
+            }
+            Message::Lookup(tx) => {
+                let owner = bank
+                    .disputable_txs
+                    .get(&tx)
+                    .map(|disputable_tx| disputable_tx.client);
+                replies
+                    .send(Reply::Owner(owner))
+                    .expect("Dispatcher stopped unexpectedly");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::bank::TxError;
+    use crate::transactions::Kind;
+
+    #[test]
+    fn test_global_checks_work_across_shards() {
+        let transactions = vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("2.0"))),
+            Transaction::new(Kind::Deposit, 2, 1, Some(amount("3.0"))),
+            Transaction::new(Kind::Withdrawal, 2, 2, Some(amount("1.0"))),
+            Transaction::new(Kind::Dispute, 2, 1, None),
+            Transaction::new(Kind::Dispute, 1, 2, None),
+            Transaction::new(Kind::Dispute, 1, 1, None),
+            Transaction::new(Kind::Chargeback, 1, 1, None),
+            Transaction::new(Kind::Dispute, 1, 2, None),
+        ];
+
+        let mut sharded = ShardedBank::new(2);
+        let outcomes = sharded.handle_transactions(transactions.clone());
+        let mut bank = Bank::new();
+        let expected = bank.handle_transactions(transactions);
+
+        assert_eq!(outcomes, expected);
+        assert_eq!(outcomes[1], TxOutcome::Rejected(TxError::DuplicateTx));
+        assert_eq!(
+            outcomes[3],
+            TxOutcome::Rejected(TxError::ClientMismatch { owner: 1 })
+        );
+        assert_eq!(outcomes[7], TxOutcome::Rejected(TxError::AccountLocked));
+        assert_eq!(sharded.get_accounts_report(), bank.get_accounts_report());
+        // a shard never keeps transactions of the clients of another shard
+        assert_eq!(
+            sharded
+                .shards
+                .iter()
+                .map(|shard| shard.disputable_txs.len())
+                .sum::<usize>(),
+            bank.disputable_txs.len()
+        );
+    }
+
+    #[test]
+    fn test_outcomes_are_streamed_in_input_order() {
+        // client 3 only shows up at the start, its outcome must not wait for the end of the input
+        let mut transactions = vec![Transaction::new(Kind::Deposit, 3, 0, Some(amount("1.0")))];
+        transactions.extend((1..5000).map(|tx| {
+            let client = (tx % 2) as ClientId;
+            match tx % 7 {
+                // some of the disputes refer to a transaction of the other client
+                0 => Transaction::new(Kind::Dispute, (tx % 3 % 2) as ClientId, tx - 2, None),
+                3 => Transaction::new(Kind::Withdrawal, client, tx, Some(amount("5.0"))),
+                _ => Transaction::new(Kind::Deposit, client, tx, Some(amount("1.0"))),
+            }
+        }));
+
+        let mut sharded = ShardedBank::new(4);
+        let mut outcomes = Vec::new();
+        sharded.handle_transactions_with(transactions.clone(), |outcome| outcomes.push(outcome));
+        let mut bank = Bank::new();
+
+        let expected = bank.handle_transactions(transactions);
+        assert!(expected.contains(&TxOutcome::Rejected(TxError::ClientMismatch { owner: 1 })));
+        assert_eq!(outcomes, expected);
+        assert_eq!(sharded.get_accounts_report(), bank.get_accounts_report());
+    }
+
+    #[test]
+    fn test_a_slow_consumer_holds_the_shards_back_without_blocking_them() {
+        let transactions: Vec<Transaction> = (0..4 * REPLY_CAPACITY as TxId)
+            .map(|tx| match tx % 5 {
+                // the disputes refer to a deposit of a client of another shard
+                4 => Transaction::new(Kind::Dispute, (tx % 3) as ClientId, tx - 1, None),
+                _ => Transaction::new(Kind::Deposit, (tx % 4) as ClientId, tx, Some(amount("1.0"))),
+            })
+            .collect();
+
+        let mut sharded = ShardedBank::new(4);
+        let mut outcomes = Vec::new();
+        sharded.handle_transactions_with(transactions.clone(), |outcome| {
+            if outcomes.len() % 1000 == 0 {
+                thread::sleep(std::time::Duration::from_millis(5));
+            }
+            outcomes.push(outcome);
+        });
+
+        assert_eq!(outcomes, Bank::new().handle_transactions(transactions));
+    }
+}
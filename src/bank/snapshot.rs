@@ -0,0 +1,244 @@
+use super::{client::Account, tx_ids::TxIdSet, Bank, ClientId, DisputableTx};
+use crate::transactions::TxId;
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"TXENGINE";
+
+/// Version of the snapshot layout, increase it whenever the serialized state changes
+pub const SNAPSHOT_VERSION: u32 = 2;
+/// Version 1 snapshots are still loaded, they end before the audit trail
+const FIRST_SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    InvalidSnapshot(String),
+    Other,
+}
+
+impl Context for SnapshotError {}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Bank snapshot failed")
+    }
+}
+
+#[derive(Serialize)]
+struct StateRef<'a> {
+    clients: &'a HashMap<ClientId, Account>,
+    disputable_txs: &'a HashMap<TxId, DisputableTx>,
+    seen_tx_ids: &'a TxIdSet,
+}
+
+#[derive(Deserialize)]
+struct State {
+    clients: HashMap<ClientId, Account>,
+    disputable_txs: HashMap<TxId, DisputableTx>,
+    seen_tx_ids: TxIdSet,
+}
+
+impl Bank {
+    /// Write the full state of the bank: accounts, disputable transactions with their dispute
+    /// state, all used transaction ids and the audit trail
+    ///
+    /// Policies are configuration and are not part of the snapshot.
+    pub fn save_snapshot<W: Write>(&self, mut writer: W) -> Result<(), SnapshotError> {
+        writer
+            .write_all(MAGIC)
+            .and_then(|_| writer.write_all(&SNAPSHOT_VERSION.to_le_bytes()))
+            .report()
+            .change_context(SnapshotError::Other)
+            .attach_printable("Failed to write the snapshot header")?;
+
+        let state = StateRef {
+            clients: &self.clients,
+            disputable_txs: &self.disputable_txs,
+            seen_tx_ids: &self.seen_tx_ids,
+        };
+        bincode::serialize_into(&mut writer, &state)
+            .and_then(|_| bincode::serialize_into(&mut writer, &self.audit_trail))
+            .report()
+            .change_context(SnapshotError::Other)
+            .attach_printable("Failed to serialize the bank state")?;
+        writer.flush().report().change_context(SnapshotError::Other)
+    }
+
+    /// Read a bank back from a snapshot written by [`Bank::save_snapshot`]
+    pub fn load_snapshot<R: Read>(mut reader: R) -> Result<Bank, SnapshotError> {
+        let mut magic = [0; MAGIC.len()];
+        let mut version = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .and_then(|_| reader.read_exact(&mut version))
+            .report()
+            .change_context(SnapshotError::InvalidSnapshot(
+                "snapshot header cannot be read".to_owned(),
+            ))?;
+        if &magic != MAGIC {
+            return Err(Report::new(SnapshotError::InvalidSnapshot(
+                "not a bank snapshot".to_owned(),
+            )));
+        }
+        let version = u32::from_le_bytes(version);
+        if !(FIRST_SNAPSHOT_VERSION..=SNAPSHOT_VERSION).contains(&version) {
+            return Err(Report::new(SnapshotError::InvalidSnapshot(format!(
+                "unsupported snapshot version {version}"
+            ))));
+        }
+
+        let state: State = bincode::deserialize_from(&mut reader)
+            .report()
+            .change_context(SnapshotError::InvalidSnapshot(
+                "bank state cannot be read".to_owned(),
+            ))?;
+        let audit_trail = match version {
+            FIRST_SNAPSHOT_VERSION => Vec::new(),
+            _ => bincode::deserialize_from(reader).report().change_context(
+                SnapshotError::InvalidSnapshot("audit trail cannot be read".to_owned()),
+            )?,
+        };
+        Ok(Bank {
+            clients: state.clients,
+            disputable_txs: state.disputable_txs,
+            seen_tx_ids: state.seen_tx_ids,
+            audit_trail,
+            ..Bank::default()
+        })
+    }
+
+    /// Write a snapshot to a file, the previous file is only replaced once the new snapshot is
+    /// completely written
+    pub fn save_snapshot_file(&self, snapshot_abs_path: &Path) -> Result<(), SnapshotError> {
+        let mut tmp_abs_path = snapshot_abs_path.as_os_str().to_owned();
+        tmp_abs_path.push(".tmp");
+
+        let file = File::create(&tmp_abs_path)
+            .report()
+            .change_context(SnapshotError::Other)
+            .attach_printable(format!("{tmp_abs_path:?} cannot be created"))?;
+        let mut writer = BufWriter::new(file);
+        self.save_snapshot(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())
+            .and_then(|file| file.sync_all())
+            .and_then(|_| std::fs::rename(&tmp_abs_path, snapshot_abs_path))
+            .report()
+            .change_context(SnapshotError::Other)
+            .attach_printable(format!("{snapshot_abs_path:?} cannot be written"))
+    }
+
+    pub fn load_snapshot_file(snapshot_abs_path: &Path) -> Result<Bank, SnapshotError> {
+        let file = File::open(snapshot_abs_path)
+            .report()
+            .change_context(SnapshotError::InvalidSnapshot(
+                "snapshot file cannot be opened".to_owned(),
+            ))
+            .attach_printable(format!("{snapshot_abs_path:?} is not a valid file"))?;
+        Bank::load_snapshot(BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::bank::{TxError, TxState};
+    use crate::transactions::{Kind, Transaction};
+
+    #[test]
+    fn test_a_restored_bank_continues_with_the_same_state() {
+        let mut bank = Bank::new();
+        bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("3.0"))),
+            Transaction::new(Kind::Deposit, 2, 2, Some(amount("1.5"))),
+            Transaction::new(Kind::Dispute, 1, 1, None),
+            Transaction::new(Kind::Withdrawal, 2, 3, Some(amount("9.0"))),
+        ]);
+
+        let mut snapshot = Vec::new();
+        bank.save_snapshot(&mut snapshot)
+            .expect("Saving snapshot failed");
+        let mut restored = Bank::load_snapshot(&snapshot[..]).expect("Loading snapshot failed");
+
+        assert_eq!(restored.get_accounts_report(), bank.get_accounts_report());
+        assert_eq!(restored.disputable_txs[&1].state, TxState::Disputed);
+        assert_eq!(
+            *restored
+                .apply(&Transaction::new(Kind::Deposit, 2, 3, Some(amount("1.0"))))
+                .unwrap_err()
+                .current_context(),
+            TxError::DuplicateTx
+        );
+        restored
+            .apply(&Transaction::new(Kind::Chargeback, 1, 1, None))
+            .expect("Chargeback failed");
+        assert!(restored.clients[&1].is_locked());
+
+        let mut snapshot = Vec::new();
+        restored
+            .save_snapshot(&mut snapshot)
+            .expect("Saving snapshot failed");
+        let restored_again = Bank::load_snapshot(&snapshot[..]).expect("Loading snapshot failed");
+        assert_eq!(restored_again.audit_trail(), restored.audit_trail());
+        assert_eq!(restored_again.audit_trail().len(), 1);
+    }
+
+    #[test]
+    fn test_snapshots_without_audit_trail_are_loaded() {
+        let mut bank = Bank::new();
+        bank.handle_transactions(vec![Transaction::new(
+            Kind::Deposit,
+            1,
+            1,
+            Some(amount("3.0")),
+        )]);
+        let mut snapshot = Vec::new();
+        bank.save_snapshot(&mut snapshot)
+            .expect("Saving snapshot failed");
+        // a version 1 snapshot is the same without the empty audit trail at the end
+        snapshot[MAGIC.len()..MAGIC.len() + 4]
+            .copy_from_slice(&FIRST_SNAPSHOT_VERSION.to_le_bytes());
+        snapshot.truncate(snapshot.len() - 8);
+
+        let restored = Bank::load_snapshot(&snapshot[..]).expect("Loading snapshot failed");
+        assert_eq!(restored.get_accounts_report(), bank.get_accounts_report());
+        assert!(restored.audit_trail().is_empty());
+    }
+
+    #[test]
+    fn test_snapshots_with_another_version_are_refused() {
+        let mut snapshot = Vec::new();
+        Bank::new()
+            .save_snapshot(&mut snapshot)
+            .expect("Saving snapshot failed");
+        snapshot[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&3_u32.to_le_bytes());
+
+        assert_eq!(
+            *Bank::load_snapshot(&snapshot[..])
+                .err()
+                .expect("Snapshot should be refused")
+                .current_context(),
+            SnapshotError::InvalidSnapshot("unsupported snapshot version 3".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_other_files_are_refused_as_snapshot() {
+        assert_eq!(
+            *Bank::load_snapshot(&b"type,client,tx,amount"[..])
+                .err()
+                .expect("Snapshot should be refused")
+                .current_context(),
+            SnapshotError::InvalidSnapshot("not a bank snapshot".to_owned())
+        );
+    }
+}
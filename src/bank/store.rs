@@ -0,0 +1,470 @@
+use super::{audit::AuditEvent, client::Account, tx_ids::TxIdSet, Bank, ClientId, DisputableTx};
+use crate::transactions::TxId;
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::Path,
+};
+
+#[derive(Debug)]
+pub enum StoreError {
+    Other,
+}
+
+impl Context for StoreError {}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Bank storage failed")
+    }
+}
+
+/// Storage of the account of every client
+pub trait AccountStore {
+    fn account(&self, client: ClientId) -> Result<Option<Account>, StoreError>;
+
+    fn store_account(&mut self, client: ClientId, account: Account) -> Result<(), StoreError>;
+
+    /// All stored accounts in any order
+    fn accounts(&self) -> Result<Vec<(ClientId, Account)>, StoreError>;
+}
+
+/// Storage of the deposits and withdrawals which can be referenced by later disputes
+pub trait TxStore {
+    fn disputable_tx(&self, tx: TxId) -> Result<Option<DisputableTx>, StoreError>;
+
+    fn store_disputable_tx(
+        &mut self,
+        tx: TxId,
+        disputable_tx: DisputableTx,
+    ) -> Result<(), StoreError>;
+}
+
+pub type MemoryAccountStore = HashMap<ClientId, Account>;
+
+pub type MemoryTxStore = HashMap<TxId, DisputableTx>;
+
+impl AccountStore for MemoryAccountStore {
+    fn account(&self, client: ClientId) -> Result<Option<Account>, StoreError> {
+        Ok(self.get(&client).cloned())
+    }
+
+    fn store_account(&mut self, client: ClientId, account: Account) -> Result<(), StoreError> {
+        self.insert(client, account);
+        Ok(())
+    }
+
+    fn accounts(&self) -> Result<Vec<(ClientId, Account)>, StoreError> {
+        Ok(self
+            .iter()
+            .map(|(client, account)| (*client, account.clone()))
+            .collect())
+    }
+}
+
+impl TxStore for MemoryTxStore {
+    fn disputable_tx(&self, tx: TxId) -> Result<Option<DisputableTx>, StoreError> {
+        Ok(self.get(&tx).cloned())
+    }
+
+    fn store_disputable_tx(
+        &mut self,
+        tx: TxId,
+        disputable_tx: DisputableTx,
+    ) -> Result<(), StoreError> {
+        self.insert(tx, disputable_tx);
+        Ok(())
+    }
+}
+
+/// Size of a single slot in a record file, the first byte tells whether the slot is used
+const SLOT_LEN: u64 = 32;
+
+/// File with one fixed size slot per key, so a record is found without any index
+///
+/// Unused slots are never written, on file systems with sparse file support they take no space.
+struct RecordFile<V> {
+    file: File,
+    values: PhantomData<V>,
+}
+
+impl<V: Serialize + DeserializeOwned> RecordFile<V> {
+    fn create(records_abs_path: &Path) -> Result<RecordFile<V>, StoreError> {
+        RecordFile::open_with(records_abs_path, true)
+    }
+
+    fn open(records_abs_path: &Path) -> Result<RecordFile<V>, StoreError> {
+        RecordFile::open_with(records_abs_path, false)
+    }
+
+    fn open_with(records_abs_path: &Path, truncate: bool) -> Result<RecordFile<V>, StoreError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(truncate)
+            .open(records_abs_path)
+            .report()
+            .change_context(StoreError::Other)
+            .attach_printable(format!("{records_abs_path:?} cannot be opened"))?;
+        Ok(RecordFile {
+            file,
+            values: PhantomData,
+        })
+    }
+
+    /// Make sure every written record is on disk
+    fn sync(&self) -> Result<(), StoreError> {
+        self.file
+            .sync_data()
+            .report()
+            .change_context(StoreError::Other)
+    }
+
+    fn read(&self, key: u64) -> Result<Option<V>, StoreError> {
+        let mut slot = [0; SLOT_LEN as usize];
+        let mut file = &self.file;
+        let read = file
+            .seek(SeekFrom::Start(key * SLOT_LEN))
+            .and_then(|_| file.read_exact(&mut slot));
+        match read {
+            Ok(()) => (),
+            // slots past the end of the file were never written
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .report()
+                    .change_context(StoreError::Other)
+                    .attach_printable(format!("Failed to read record {key}"))
+            }
+        }
+        if slot[0] == 0 {
+            return Ok(None);
+        }
+        bincode::deserialize(&slot[1..])
+            .map(Some)
+            .report()
+            .change_context(StoreError::Other)
+            .attach_printable(format!("Record {key} is corrupt"))
+    }
+
+    fn write(&mut self, key: u64, value: &V) -> Result<(), StoreError> {
+        let mut slot = vec![1];
+        bincode::serialize_into(&mut slot, value)
+            .report()
+            .change_context(StoreError::Other)?;
+        if slot.len() as u64 > SLOT_LEN {
+            return Err(Report::new(StoreError::Other))
+                .attach_printable(format!("Record {key} does not fit in a slot"));
+        }
+        slot.resize(SLOT_LEN as usize, 0);
+        self.file
+            .seek(SeekFrom::Start(key * SLOT_LEN))
+            .and_then(|_| self.file.write_all(&slot))
+            .report()
+            .change_context(StoreError::Other)
+            .attach_printable(format!("Failed to write record {key}"))
+    }
+
+    fn slots(&self) -> Result<u64, StoreError> {
+        self.file
+            .metadata()
+            .map(|metadata| metadata.len() / SLOT_LEN)
+            .report()
+            .change_context(StoreError::Other)
+    }
+}
+
+/// Accounts kept in a local file instead of in memory
+pub struct FileAccountStore {
+    records: RecordFile<Account>,
+}
+
+impl FileAccountStore {
+    /// Start an empty store, an existing file is truncated
+    pub fn create(accounts_abs_path: &Path) -> Result<FileAccountStore, StoreError> {
+        Ok(FileAccountStore {
+            records: RecordFile::create(accounts_abs_path)?,
+        })
+    }
+
+    /// Continue with the accounts of an existing file, a missing file is created empty
+    pub fn open(accounts_abs_path: &Path) -> Result<FileAccountStore, StoreError> {
+        Ok(FileAccountStore {
+            records: RecordFile::open(accounts_abs_path)?,
+        })
+    }
+}
+
+impl AccountStore for FileAccountStore {
+    fn account(&self, client: ClientId) -> Result<Option<Account>, StoreError> {
+        self.records.read(u64::from(client))
+    }
+
+    fn store_account(&mut self, client: ClientId, account: Account) -> Result<(), StoreError> {
+        self.records.write(u64::from(client), &account)
+    }
+
+    fn accounts(&self) -> Result<Vec<(ClientId, Account)>, StoreError> {
+        let mut accounts = Vec::new();
+        for client in 0..self.records.slots()? {
+            if let Some(account) = self.records.read(client)? {
+                let client = ClientId::try_from(client).expect("Slots are indexed by client id");
+                accounts.push((client, account));
+            }
+        }
+        Ok(accounts)
+    }
+}
+
+/// Disputable transactions kept in a local file, so their number is not limited by memory
+pub struct FileTxStore {
+    records: RecordFile<DisputableTx>,
+}
+
+impl FileTxStore {
+    /// Start an empty store, an existing file is truncated
+    pub fn create(txs_abs_path: &Path) -> Result<FileTxStore, StoreError> {
+        Ok(FileTxStore {
+            records: RecordFile::create(txs_abs_path)?,
+        })
+    }
+
+    /// Continue with the transactions of an existing file, a missing file is created empty
+    pub fn open(txs_abs_path: &Path) -> Result<FileTxStore, StoreError> {
+        Ok(FileTxStore {
+            records: RecordFile::open(txs_abs_path)?,
+        })
+    }
+}
+
+impl TxStore for FileTxStore {
+    fn disputable_tx(&self, tx: TxId) -> Result<Option<DisputableTx>, StoreError> {
+        self.records.read(u64::from(tx))
+    }
+
+    fn store_disputable_tx(
+        &mut self,
+        tx: TxId,
+        disputable_tx: DisputableTx,
+    ) -> Result<(), StoreError> {
+        self.records.write(u64::from(tx), &disputable_tx)
+    }
+}
+
+/// Accounts which can be read but never stored, to test how a failing store is handled
+#[cfg(test)]
+pub(crate) struct ReadOnlyAccounts(pub(crate) MemoryAccountStore);
+
+#[cfg(test)]
+impl AccountStore for ReadOnlyAccounts {
+    fn account(&self, client: ClientId) -> Result<Option<Account>, StoreError> {
+        self.0.account(client)
+    }
+
+    fn store_account(&mut self, _: ClientId, _: Account) -> Result<(), StoreError> {
+        Err(Report::new(StoreError::Other))
+    }
+
+    fn accounts(&self) -> Result<Vec<(ClientId, Account)>, StoreError> {
+        self.0.accounts()
+    }
+}
+
+const ACCOUNTS_FILE: &str = "accounts";
+const TXS_FILE: &str = "txs";
+/// The used transaction ids and the audit trail, which are not part of the record files
+const IDS_FILE: &str = "ids";
+
+/// A bank kept in a directory, which a later run continues from
+///
+/// The accounts and disputable transactions are written to their files while the transactions
+/// are applied, the rest of the state only by [`Bank::save_store_dir`]. A run which stops before
+/// that leaves the directory inconsistent.
+impl Bank<FileAccountStore, FileTxStore> {
+    /// Open the stores in a directory, which is created when missing
+    pub fn open_store_dir(
+        store_abs_path: &Path,
+    ) -> Result<Bank<FileAccountStore, FileTxStore>, StoreError> {
+        std::fs::create_dir_all(store_abs_path)
+            .report()
+            .change_context(StoreError::Other)
+            .attach_printable(format!("{store_abs_path:?} cannot be created"))?;
+        let mut bank = Bank::with_stores(
+            FileAccountStore::open(&store_abs_path.join(ACCOUNTS_FILE))?,
+            FileTxStore::open(&store_abs_path.join(TXS_FILE))?,
+        );
+
+        let ids_abs_path = store_abs_path.join(IDS_FILE);
+        let file = match File::open(&ids_abs_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(bank),
+            Err(err) => {
+                return Err(err)
+                    .report()
+                    .change_context(StoreError::Other)
+                    .attach_printable(format!("{ids_abs_path:?} cannot be opened"))
+            }
+        };
+        let (seen_tx_ids, audit_trail): (TxIdSet, Vec<AuditEvent>) =
+            bincode::deserialize_from(BufReader::new(file))
+                .report()
+                .change_context(StoreError::Other)
+                .attach_printable(format!("{ids_abs_path:?} is corrupt"))?;
+        bank.seen_tx_ids = seen_tx_ids;
+        bank.audit_trail = audit_trail;
+        Ok(bank)
+    }
+
+    /// Sync the record files and save the rest of the state next to them
+    pub fn save_store_dir(&self, store_abs_path: &Path) -> Result<(), StoreError> {
+        self.clients.records.sync()?;
+        self.disputable_txs.records.sync()?;
+
+        let ids_abs_path = store_abs_path.join(IDS_FILE);
+        let tmp_abs_path = store_abs_path.join(format!("{IDS_FILE}.tmp"));
+        let file = File::create(&tmp_abs_path)
+            .report()
+            .change_context(StoreError::Other)
+            .attach_printable(format!("{tmp_abs_path:?} cannot be created"))?;
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, &(&self.seen_tx_ids, &self.audit_trail))
+            .report()
+            .change_context(StoreError::Other)?;
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())
+            .and_then(|file| file.sync_all())
+            .and_then(|_| std::fs::rename(&tmp_abs_path, &ids_abs_path))
+            .report()
+            .change_context(StoreError::Other)
+            .attach_printable(format!("{ids_abs_path:?} cannot be written"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::amount;
+    use crate::bank::{TxError, TxOutcome, TxState};
+    use crate::transactions::{Kind, Transaction};
+
+    #[test]
+    fn test_file_stores_return_what_was_stored() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let mut accounts =
+            FileAccountStore::create(&dir.path().join("accounts")).expect("Creating store failed");
+        let mut txs = FileTxStore::create(&dir.path().join("txs")).expect("Creating store failed");
+
+        let mut account = Account::new();
+        account.deposit(amount("2.5")).expect("Deposit failed");
+        accounts
+            .store_account(7, account)
+            .expect("Storing account failed");
+        let disputable_tx = DisputableTx {
+            client: 7,
+            kind: Kind::Withdrawal,
+            amount: amount("1.25"),
+            state: TxState::Disputed,
+        };
+        txs.store_disputable_tx(TxId::MAX, disputable_tx.clone())
+            .expect("Storing transaction failed");
+
+        let stored = accounts
+            .account(7)
+            .expect("Reading account failed")
+            .expect("Account is stored");
+        assert_eq!(stored.get_available_funds(), amount("2.5"));
+        assert!(accounts
+            .account(6)
+            .expect("Reading account failed")
+            .is_none());
+        assert!(accounts
+            .account(ClientId::MAX)
+            .expect("Reading account failed")
+            .is_none());
+        assert_eq!(
+            accounts
+                .accounts()
+                .expect("Reading accounts failed")
+                .into_iter()
+                .map(|(client, _)| client)
+                .collect::<Vec<_>>(),
+            vec![7]
+        );
+        assert_eq!(
+            txs.disputable_tx(TxId::MAX)
+                .expect("Reading transaction failed"),
+            Some(disputable_tx)
+        );
+        assert_eq!(
+            txs.disputable_tx(0).expect("Reading transaction failed"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_opened_file_stores_keep_their_records() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let accounts_file = dir.path().join("accounts");
+        let mut account = Account::new();
+        account.deposit(amount("2.5")).expect("Deposit failed");
+        FileAccountStore::create(&accounts_file)
+            .expect("Creating store failed")
+            .store_account(7, account)
+            .expect("Storing account failed");
+
+        let reopened = FileAccountStore::open(&accounts_file).expect("Opening store failed");
+        assert_eq!(
+            reopened
+                .account(7)
+                .expect("Reading account failed")
+                .map(|account| account.get_available_funds()),
+            Some(amount("2.5"))
+        );
+        drop(reopened);
+        let recreated = FileAccountStore::create(&accounts_file).expect("Creating store failed");
+        assert!(recreated
+            .accounts()
+            .expect("Reading accounts failed")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_a_store_dir_continues_with_the_saved_state() {
+        let dir = tempfile::tempdir().expect("Creating temporary directory failed");
+        let store_dir = dir.path().join("bank");
+
+        let mut bank = Bank::open_store_dir(&store_dir).expect("Opening store failed");
+        bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("3.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 2, Some(amount("5.0"))),
+        ]);
+        bank.save_store_dir(&store_dir)
+            .expect("Saving store failed");
+        drop(bank);
+
+        let mut reopened = Bank::open_store_dir(&store_dir).expect("Opening store failed");
+        assert_eq!(
+            reopened.handle_transactions(vec![
+                Transaction::new(Kind::Deposit, 1, 2, Some(amount("1.0"))),
+                Transaction::new(Kind::Dispute, 1, 1, None),
+            ]),
+            vec![
+                TxOutcome::Rejected(TxError::DuplicateTx),
+                TxOutcome::Accepted
+            ]
+        );
+        let account = reopened
+            .clients
+            .account(1)
+            .expect("Reading account failed")
+            .expect("Account is stored");
+        assert_eq!(account.get_held_funds(), amount("3.0"));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::transactions::TxId;
+use serde::{de, ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+
+const PAGE_BITS: u32 = 16;
+const WORDS_PER_PAGE: usize = (1 << PAGE_BITS) / u64::BITS as usize;
+const PAGES: usize = 1 << (TxId::BITS - PAGE_BITS);
+
+type Page = [u64; WORDS_PER_PAGE];
+
+/// Set of transaction ids, one bit per possible id
+///
+/// The bits are kept in pages of 8 KiB which are only allocated when an id in their range is
+/// inserted. Dense ids cost one bit each, the whole `TxId` space needs 512 MiB at most.
+#[derive(Clone)]
+pub struct TxIdSet {
+    pages: Vec<Option<Box<Page>>>,
+    len: usize,
+}
+
+impl Default for TxIdSet {
+    fn default() -> TxIdSet {
+        TxIdSet {
+            pages: (0..PAGES).map(|_| None).collect(),
+            len: 0,
+        }
+    }
+}
+
+fn locate(id: TxId) -> (usize, usize, u64) {
+    let page = (id >> PAGE_BITS) as usize;
+    let bit = (id & ((1 << PAGE_BITS) - 1)) as usize;
+    (
+        page,
+        bit / u64::BITS as usize,
+        1 << (bit % u64::BITS as usize),
+    )
+}
+
+impl TxIdSet {
+    pub fn new() -> TxIdSet {
+        TxIdSet::default()
+    }
+
+    pub fn contains(&self, id: TxId) -> bool {
+        let (page, word, mask) = locate(id);
+        self.pages[page]
+            .as_ref()
+            .is_some_and(|page| page[word] & mask != 0)
+    }
+
+    /// Add an id to the set, returns whether it was not present yet
+    pub fn insert(&mut self, id: TxId) -> bool {
+        let (page, word, mask) = locate(id);
+        let page = self.pages[page].get_or_insert_with(|| Box::new([0; WORDS_PER_PAGE]));
+        let inserted = page[word] & mask == 0;
+        page[word] |= mask;
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Only the allocated pages are serialized, together with their index
+impl Serialize for TxIdSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let allocated = self.pages.iter().filter(|page| page.is_some()).count();
+        let mut seq = serializer.serialize_seq(Some(allocated))?;
+        for (idx, page) in self.pages.iter().enumerate() {
+            if let Some(page) = page {
+                seq.serialize_element(&(idx, &page[..]))?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for TxIdSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TxIdSet, D::Error> {
+        let mut ids = TxIdSet::new();
+        for (idx, words) in Vec::<(usize, Vec<u64>)>::deserialize(deserializer)? {
+            let page: Page = words
+                .try_into()
+                .map_err(|_| de::Error::custom("transaction id page has an invalid size"))?;
+            let slot = ids
+                .pages
+                .get_mut(idx)
+                .ok_or_else(|| de::Error::custom("transaction id page is out of range"))?;
+            if slot.is_some() {
+                return Err(de::Error::custom("transaction id page is duplicated"));
+            }
+            ids.len += page
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum::<usize>();
+            *slot = Some(Box::new(page));
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_only_inserted_once() {
+        let mut ids = TxIdSet::new();
+
+        assert!(ids.insert(1));
+        assert!(!ids.insert(1));
+        assert!(ids.contains(1));
+        assert!(!ids.contains(2));
+        assert_eq!(ids.len(), 1);
+    }
+
+    #[test]
+    fn test_the_full_id_range_can_be_stored() {
+        let mut ids = TxIdSet::new();
+
+        for id in [0, 63, 64, 65_535, 65_536, TxId::MAX - 1, TxId::MAX] {
+            assert!(ids.insert(id));
+        }
+        for id in [0, 63, 64, 65_535, 65_536, TxId::MAX - 1, TxId::MAX] {
+            assert!(ids.contains(id));
+        }
+        assert!(!ids.contains(1));
+        assert!(!ids.contains(TxId::MAX - 2));
+        assert_eq!(ids.len(), 7);
+    }
+
+    #[test]
+    fn test_pages_are_only_allocated_when_used() {
+        let mut ids = TxIdSet::new();
+        ids.insert(3);
+        ids.insert(70_000);
+
+        assert_eq!(ids.pages.iter().filter(|page| page.is_some()).count(), 2);
+    }
+}
@@ -0,0 +1,41 @@
+use clap::Parser;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tx_engine::{bank::Bank, cli::PolicyArgs, server};
+
+/// Serve one shared bank to many clients at the same time
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Answer JSON requests over HTTP instead of CSV records over TCP
+    #[arg(long)]
+    http: bool,
+    #[command(flatten)]
+    policies: PolicyArgs,
+    /// Address to listen on
+    #[arg(default_value = "127.0.0.1:7878")]
+    address: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), String> {
+    let cli = Cli::parse();
+    env_logger::init();
+
+    let address = &cli.address;
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|err| format!("Cannot listen on {address}: {err}"))?;
+    eprintln!("Listening on {address}");
+
+    let bank = Arc::new(Mutex::new(cli.policies.apply(Bank::new())));
+    let served = if cli.http {
+        server::http::serve(listener, bank).await
+    } else {
+        server::serve(listener, bank).await
+    };
+    served.map_err(|err| {
+        log::error!("\n{err:?}");
+        "Server stopped unexpectedly".to_owned()
+    })
+}
@@ -0,0 +1,27 @@
+use crate::bank::{
+    store::{AccountStore, TxStore},
+    Bank, DisputePolicy, FeePolicy, LockedAccountPolicy,
+};
+use clap::Args;
+
+/// Options selecting the policies of a bank, shared by the binaries
+#[derive(Args)]
+pub struct PolicyArgs {
+    /// How disputes of withdrawals are handled [not-disputable, credit-back, reserve-held]
+    #[arg(long, value_name = "POLICY", default_value = "credit-back")]
+    dispute_policy: DisputePolicy,
+    /// Which transactions a locked account accepts [reject-all, deposits-only, disputes-only]
+    #[arg(long, value_name = "POLICY", default_value = "reject-all")]
+    locked_account_policy: LockedAccountPolicy,
+    /// Whether a fee can be charged beyond the available funds [require-funds, allow-overdraft]
+    #[arg(long, value_name = "POLICY", default_value = "require-funds")]
+    fee_policy: FeePolicy,
+}
+
+impl PolicyArgs {
+    pub fn apply<A: AccountStore, T: TxStore>(&self, bank: Bank<A, T>) -> Bank<A, T> {
+        bank.with_dispute_policy(self.dispute_policy)
+            .with_locked_account_policy(self.locked_account_policy)
+            .with_fee_policy(self.fee_policy)
+    }
+}
@@ -1,4 +1,7 @@
+pub mod amount;
 pub mod bank;
+pub mod cli;
+pub mod server;
 pub mod transactions;
 
 pub use bank::client;
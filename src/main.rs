@@ -1,40 +1,788 @@
-use std::path::PathBuf;
-use tx_engine::{bank::BankError, transactions, transactions::ParseTxError};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
+use error_stack::Report;
+use log::LevelFilter;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter, Read},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use tx_engine::{
+    bank::audit::AdminError,
+    bank::journal::{JournalError, DEFAULT_SYNC_EVERY},
+    bank::rejections::{read_rejections, RejectedTx, RejectionWriter},
+    bank::report::{self, ReportFormat},
+    bank::snapshot::SnapshotError,
+    bank::store::{AccountStore, StoreError, TxStore},
+    bank::{Bank, BankError, ClientId, TxError},
+    cli::PolicyArgs,
+    transactions::{InputFormat, ParseMode, ParseTxError, RowError, TransactionReader},
+};
 
-fn main() -> Result<(), String> {
-    env_logger::init();
+/// Exit code when the transactions, a state or a journal are invalid
+const EXIT_INVALID_INPUT: u8 = 65;
+/// Exit code when the input is fine but processing it failed
+const EXIT_INTERNAL_ERROR: u8 = 70;
 
-    let mut args = std::env::args().into_iter();
+/// Apply transactions to client accounts and report their balances
+///
+/// Without a command the transactions are processed, like with `process`.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Log level, overrides `RUST_LOG`
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<LevelFilter>,
 
-    args.next(); // skip the app name
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    let transactions_abs_path = PathBuf::from(args.next().expect("No transaction CSV file given!"));
+    #[command(flatten)]
+    process: ProcessArgs,
+}
 
-    let transactions = match transactions::parse_transactions(&transactions_abs_path) {
-        Err(err) => {
-            log::error!("\n{err:?}");
-            match err.current_context() {
-                ParseTxError::InvalidInput(msg) => return Err(format!("Invalid input: {msg}")),
-                ParseTxError::Other => return Err("Internal error!".to_owned()),
+#[derive(Subcommand)]
+enum Command {
+    /// Apply transactions and print the accounts report
+    Process(ProcessArgs),
+    /// Check every record and show which transactions would be rejected, without saving anything
+    Validate(ValidateArgs),
+    /// Apply transactions and print the movements of one client with the balance after each
+    Statement(StatementArgs),
+    /// Print the accounts report of a saved state
+    Report {
+        /// Snapshot written with `--state-out`
+        #[arg(long)]
+        state: PathBuf,
+        /// Print every lock and unlock of an account instead
+        #[arg(long)]
+        audit: bool,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Unlock an account of a saved state after a chargeback
+    Unlock {
+        /// Snapshot which is updated in place
+        #[arg(long)]
+        state: PathBuf,
+        #[arg(long)]
+        client: ClientId,
+        /// Id of the operator, kept in the audit trail
+        #[arg(long)]
+        operator: String,
+        /// Kept in the audit trail
+        #[arg(long)]
+        reason: String,
+    },
+    /// Replay a journal on top of a saved state and print the accounts report
+    Replay {
+        /// Journal left behind by an interrupted `process --journal`
+        #[arg(long)]
+        journal: PathBuf,
+        /// Snapshot the journal was written on top of, an empty bank by default
+        #[arg(long)]
+        state_in: Option<PathBuf>,
+        /// Save the replayed state, after which the journal is removed
+        #[arg(long)]
+        state_out: Option<PathBuf>,
+        /// Policies of the run which wrote the journal
+        #[command(flatten)]
+        policies: PolicyArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(Args)]
+struct InputArgs {
+    /// Skip invalid records instead of stopping at the first one
+    #[arg(long)]
+    lenient: bool,
+    /// Format of all inputs, by default from each file extension [csv, json, jsonl]
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<InputFormat>,
+    /// Read in order as one stream of transactions, `-` is std in
+    #[arg(required = true, value_name = "TRANSACTIONS")]
+    transactions_abs_paths: Vec<PathBuf>,
+}
+
+impl InputArgs {
+    fn parse_mode(&self) -> ParseMode {
+        match self.lenient {
+            true => ParseMode::Lenient,
+            false => ParseMode::Strict,
+        }
+    }
+
+    fn reader(&self, path: &Path) -> Result<TransactionReader<Box<dyn Read>>, CliError> {
+        let input_format = self.format.unwrap_or_else(|| InputFormat::from_path(path));
+        Ok(TransactionReader::from_path_with_format(path, input_format)
+            .map_err(parse_error)?
+            .with_mode(self.parse_mode()))
+    }
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Check the transactions against a snapshot instead of an empty bank
+    #[arg(long, value_name = "STATE")]
+    state_in: Option<PathBuf>,
+    #[command(flatten)]
+    policies: PolicyArgs,
+}
+
+#[derive(Args)]
+struct StatementArgs {
+    /// Client whose movements are listed
+    #[arg(long)]
+    client: ClientId,
+    /// First position in the stream of transactions, starting at 1
+    #[arg(long, value_name = "POSITION", default_value_t = 1)]
+    from: u64,
+    /// Last position in the stream of transactions, up to the end by default
+    #[arg(long, value_name = "POSITION", default_value_t = u64::MAX)]
+    to: u64,
+    /// Load the bank from a snapshot, positions start after it
+    #[arg(long, value_name = "STATE")]
+    state_in: Option<PathBuf>,
+    #[command(flatten)]
+    input: InputArgs,
+    #[command(flatten)]
+    policies: PolicyArgs,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct ProcessArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Write rejected transactions and skipped records to a CSV file
+    #[arg(long, value_name = "FILE")]
+    rejected: Option<PathBuf>,
+    /// Load the bank from a snapshot before processing
+    #[arg(long, value_name = "STATE")]
+    state_in: Option<PathBuf>,
+    /// Save the bank to a snapshot after processing
+    #[arg(long, value_name = "STATE")]
+    state_out: Option<PathBuf>,
+    /// Journal every transaction which changes the bank, to resume after a crash
+    #[arg(long, value_name = "JOURNAL")]
+    journal: Option<PathBuf>,
+    /// Keep the bank in files in a directory instead of in memory, the next run continues from it
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["state_in", "state_out", "journal"]
+    )]
+    store_dir: Option<PathBuf>,
+    #[command(flatten)]
+    policies: PolicyArgs,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// Format of the accounts report [csv, json, jsonl, table]
+    #[arg(long, value_name = "FORMAT", default_value = "csv")]
+    output: ReportFormat,
+    /// Single character delimiter of the CSV report, or `tab`
+    #[arg(long, value_name = "CHAR", value_parser = parse_delimiter)]
+    delimiter: Option<u8>,
+    /// Leave out the header row of the CSV report
+    #[arg(long)]
+    no_header: bool,
+}
+
+impl OutputArgs {
+    fn report_format(&self) -> ReportFormat {
+        match self.output {
+            ReportFormat::Csv { delimiter, .. } => ReportFormat::Csv {
+                delimiter: self.delimiter.unwrap_or(delimiter),
+                header: !self.no_header,
+            },
+            format if self.delimiter.is_none() && !self.no_header => format,
+            _ => Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--delimiter and --no-header only apply to CSV output",
+                )
+                .exit(),
+        }
+    }
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter.as_bytes() {
+        _ if delimiter == "tab" => Ok(b'\t'),
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err("expected a single ASCII character or `tab`".to_owned()),
+    }
+}
+
+/// Failure of a command, with the exit code telling invalid input apart from internal errors
+struct CliError {
+    exit_code: u8,
+    message: String,
+}
+
+impl CliError {
+    fn invalid_input(message: impl Into<String>) -> CliError {
+        CliError {
+            exit_code: EXIT_INVALID_INPUT,
+            message: message.into(),
+        }
+    }
+
+    fn internal(message: impl Into<String>) -> CliError {
+        CliError {
+            exit_code: EXIT_INTERNAL_ERROR,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(&self.message)
+    }
+}
+
+fn parse_error(err: Report<ParseTxError>) -> CliError {
+    log::error!("\n{err:?}");
+    match err.current_context() {
+        ParseTxError::InvalidInput(msg) => CliError::invalid_input(format!("Invalid input: {msg}")),
+        ParseTxError::Other => CliError::internal("Internal error!"),
+    }
+}
+
+fn snapshot_error(err: Report<SnapshotError>) -> CliError {
+    log::error!("\n{err:?}");
+    match err.current_context() {
+        SnapshotError::InvalidSnapshot(msg) => {
+            CliError::invalid_input(format!("Invalid state: {msg}"))
+        }
+        SnapshotError::Other => CliError::internal("Cannot save the bank state"),
+    }
+}
+
+fn journal_error(err: Report<JournalError>) -> CliError {
+    log::error!("\n{err:?}");
+    match err.current_context() {
+        JournalError::InvalidJournal(msg) => {
+            CliError::invalid_input(format!("Invalid journal: {msg}"))
+        }
+        JournalError::Other => CliError::internal("Cannot journal the transactions"),
+    }
+}
+
+fn store_error(err: Report<StoreError>) -> CliError {
+    log::error!("\n{err:?}");
+    CliError::internal("Cannot store the bank state")
+}
+
+fn rejection_error(err: Report<BankError>) -> CliError {
+    log::error!("\n{err:?}");
+    CliError::internal("Cannot export rejected transactions to CSV")
+}
+
+/// Rows read back from the rejected transactions file of an interrupted run
+type InterruptedRows = Peekable<Box<dyn Iterator<Item = Result<RejectedTx, Report<BankError>>>>>;
+
+/// Optional CSV output of rejected transactions and skipped records, in input order
+///
+/// Every row is flushed right away, so a resumed run finds the rows of the interrupted one.
+#[derive(Default)]
+struct Rejections {
+    writer: Option<RejectionWriter<BufWriter<File>>>,
+    skipped: usize,
+    /// Rows of an interrupted run, kept for the transactions which are not applied again
+    interrupted: Option<InterruptedRows>,
+    interrupted_abs_path: Option<PathBuf>,
+}
+
+impl Rejections {
+    /// Write to a new file, or when resuming a run rewrite its file
+    fn create(rejected_abs_path: &Path, resume_position: u64) -> Result<Rejections, CliError> {
+        let mut rejections = Rejections::default();
+        if resume_position > 0 && rejected_abs_path.is_file() {
+            let mut interrupted_abs_path = rejected_abs_path.as_os_str().to_owned();
+            interrupted_abs_path.push(".interrupted");
+            let interrupted_abs_path = PathBuf::from(interrupted_abs_path);
+            let file = std::fs::rename(rejected_abs_path, &interrupted_abs_path)
+                .and_then(|_| File::open(&interrupted_abs_path))
+                .map_err(|err| {
+                    CliError::internal(format!("Cannot move {rejected_abs_path:?} aside: {err}"))
+                })?;
+            let rows: Box<dyn Iterator<Item = _>> = Box::new(read_rejections(BufReader::new(file)));
+            rejections.interrupted = Some(rows.peekable());
+            rejections.interrupted_abs_path = Some(interrupted_abs_path);
+        }
+        let file = File::create(rejected_abs_path).map_err(|err| {
+            CliError::internal(format!("Cannot create {rejected_abs_path:?}: {err}"))
+        })?;
+        rejections.writer =
+            Some(RejectionWriter::new(BufWriter::new(file)).map_err(rejection_error)?);
+        Ok(rejections)
+    }
+
+    fn write(&mut self, rejected: &RejectedTx) -> Result<(), CliError> {
+        match &mut self.writer {
+            Some(writer) => writer
+                .write(rejected)
+                .and_then(|_| writer.flush())
+                .map_err(rejection_error),
+            None => Ok(()),
+        }
+    }
+
+    /// The next row of the interrupted run when it was read from this line of an input
+    fn next_interrupted(&mut self, path: &Path, line: u64) -> Result<Option<RejectedTx>, CliError> {
+        let Some(interrupted) = &mut self.interrupted else {
+            return Ok(None);
+        };
+        match interrupted.next_if(|row| !matches!(row, Ok(row) if !row.is_from(path, line))) {
+            Some(row) => row.map(Some).map_err(rejection_error),
+            None => Ok(None),
+        }
+    }
+
+    /// Keep the row of a transaction which the interrupted run already applied
+    fn keep_interrupted(&mut self, path: &Path, line: u64) -> Result<(), CliError> {
+        match self.next_interrupted(path, line)? {
+            Some(rejected) => self.write(&rejected),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the remaining rows of the interrupted run, the transactions are applied again
+    fn resume(&mut self) -> Result<(), CliError> {
+        self.interrupted = None;
+        match self.interrupted_abs_path.take() {
+            Some(path) => std::fs::remove_file(&path)
+                .map_err(|err| CliError::internal(format!("Cannot remove {path:?}: {err}"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the row errors of an input which were not written yet, returns how many
+    fn write_skipped(&mut self, path: &Path, row_errors: &[RowError]) -> Result<usize, CliError> {
+        for row_error in &row_errors[self.skipped..] {
+            // written again from the input itself
+            self.next_interrupted(path, row_error.line)?;
+            self.write(&RejectedTx::malformed(path, row_error))?;
+        }
+        let written = row_errors.len() - self.skipped;
+        self.skipped = row_errors.len();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), CliError> {
+        match &mut self.writer {
+            Some(writer) => writer.flush().map_err(rejection_error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Outcomes over all inputs, `position` counts the transactions read so far
+#[derive(Default)]
+struct Counts {
+    position: u64,
+    accepted: usize,
+    rejected: usize,
+    skipped: usize,
+}
+
+/// Apply the transactions of one input, the first `resume_position` transactions of all inputs
+/// together are already in the bank
+///
+/// Records skipped before the resumed transactions are only written, not counted, the same as
+/// their rejections.
+fn process_input<R: Read, A: AccountStore, T: TxStore>(
+    bank: &mut Bank<A, T>,
+    path: &Path,
+    mut transactions: TransactionReader<R>,
+    resume_position: u64,
+    counts: &mut Counts,
+    rejections: &mut Rejections,
+) -> Result<(), CliError> {
+    // the row errors of every input start from the beginning
+    rejections.skipped = 0;
+    while let Some(transaction) = transactions.next() {
+        let tx = transaction.map_err(parse_error)?;
+        // records skipped while reading come before the transaction itself
+        let skipped = rejections.write_skipped(path, transactions.row_errors())?;
+        if counts.position >= resume_position {
+            counts.skipped += skipped;
+        }
+        counts.position += 1;
+        if counts.position <= resume_position {
+            rejections.keep_interrupted(path, transactions.line())?;
+            continue;
+        }
+        if counts.position == resume_position + 1 {
+            rejections.resume()?;
+        }
+        match bank.apply(&tx) {
+            Ok(()) => counts.accepted += 1,
+            Err(err) if *err.current_context() == TxError::Journal => {
+                log::error!("\n{err:?}");
+                return Err(CliError::internal("Cannot journal the transactions"));
+            }
+            Err(err) if *err.current_context() == TxError::Storage => {
+                log::error!("\n{err:?}");
+                return Err(CliError::internal("Cannot store the bank state"));
+            }
+            Err(err) => {
+                counts.rejected += 1;
+                rejections.write(&RejectedTx::new(
+                    path,
+                    transactions.line(),
+                    &tx,
+                    err.current_context(),
+                ))?;
             }
         }
-        Ok(transactions) => transactions,
+    }
+    let skipped = rejections.write_skipped(path, transactions.row_errors())?;
+    if counts.position >= resume_position {
+        counts.skipped += skipped;
+    }
+    Ok(())
+}
+
+fn load_bank(state_in_abs_path: Option<&Path>) -> Result<Bank, CliError> {
+    match state_in_abs_path {
+        Some(path) => Bank::load_snapshot_file(path).map_err(snapshot_error),
+        None => Ok(Bank::new()),
+    }
+}
+
+/// Sync the journal and, when a new state is saved, remove the journal which it contains
+fn save_bank(
+    bank: &mut Bank,
+    state_out_abs_path: Option<&Path>,
+    journal_abs_path: Option<&Path>,
+) -> Result<(), CliError> {
+    bank.sync_journal().map_err(journal_error)?;
+    if let Some(path) = state_out_abs_path {
+        bank.save_snapshot_file(path).map_err(snapshot_error)?;
+        // the snapshot contains everything which was journaled
+        if let Some(journal_path) = journal_abs_path {
+            std::fs::remove_file(journal_path).map_err(|err| {
+                CliError::internal(format!("Cannot remove {journal_path:?}: {err}"))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn output_report<A: AccountStore, T: TxStore>(
+    bank: &Bank<A, T>,
+    output: &OutputArgs,
+) -> Result<(), CliError> {
+    let mut writer = std::io::stdout().lock();
+    bank.output_accounts_report_as(&mut writer, output.report_format())
+        .map_err(|err| {
+            log::error!("\n{err:?}");
+            CliError::internal("Cannot export client report from input")
+        })
+}
+
+/// Apply the transactions of all inputs, with a summary on std err when records are skipped
+fn process_inputs<A: AccountStore, T: TxStore>(
+    bank: &mut Bank<A, T>,
+    input: &InputArgs,
+    resume_position: u64,
+    rejections: &mut Rejections,
+) -> Result<(), CliError> {
+    let mut counts = Counts::default();
+    for path in &input.transactions_abs_paths {
+        process_input(
+            bank,
+            path,
+            input.reader(path)?,
+            resume_position,
+            &mut counts,
+            rejections,
+        )?;
+    }
+    // every transaction was already applied by the interrupted run
+    rejections.resume()?;
+    rejections.flush()?;
+
+    if input.lenient {
+        let Counts {
+            accepted,
+            rejected,
+            skipped,
+            ..
+        } = counts;
+        eprintln!(
+            "{accepted} transactions accepted, {rejected} rejected, {skipped} invalid records skipped"
+        );
+    }
+    Ok(())
+}
+
+fn process(args: &ProcessArgs) -> Result<(), CliError> {
+    let rejections = |resume_position| match &args.rejected {
+        Some(path) => Rejections::create(path, resume_position),
+        None => Ok(Rejections::default()),
     };
 
-    let mut bank = tx_engine::bank::Bank::new();
-    if let Err(err) = bank.handle_transactions(transactions) {
+    if let Some(store_dir) = &args.store_dir {
+        let mut bank = args
+            .policies
+            .apply(Bank::open_store_dir(store_dir).map_err(store_error)?);
+        process_inputs(&mut bank, &args.input, 0, &mut rejections(0)?)?;
+        bank.save_store_dir(store_dir).map_err(store_error)?;
+        return output_report(&bank, &args.output);
+    }
+
+    let mut bank = args.policies.apply(load_bank(args.state_in.as_deref())?);
+    let resume_position = match &args.journal {
+        Some(path) => bank
+            .recover_journal(path, DEFAULT_SYNC_EVERY)
+            .map_err(journal_error)?,
+        None => 0,
+    };
+    if resume_position > 0 {
+        eprintln!("Resuming after {resume_position} transactions from the journal");
+    }
+    process_inputs(
+        &mut bank,
+        &args.input,
+        resume_position,
+        &mut rejections(resume_position)?,
+    )?;
+
+    if args.state_out.is_none() {
+        // the next run with this journal builds on its state instead of resuming the input
+        bank.complete_journal().map_err(journal_error)?;
+    }
+    save_bank(
+        &mut bank,
+        args.state_out.as_deref(),
+        args.journal.as_deref(),
+    )?;
+    output_report(&bank, &args.output)
+}
+
+/// Print the row errors of an input which were not printed yet
+fn print_row_errors(path: &Path, row_errors: &[RowError], printed: &mut usize) {
+    for row_error in &row_errors[*printed..] {
+        if let ParseTxError::InvalidInput(msg) = &row_error.error {
+            println!("{}:{}: {msg}", path.display(), row_error.line);
+        }
+    }
+    *printed = row_errors.len();
+}
+
+/// Run the transactions through a dry run of the bank, without saving anything
+///
+/// Every invalid record and rejected transaction is listed on std out, a summary with the
+/// reasons of the rejections on std err. The input is invalid when any record is.
+fn validate(args: &ValidateArgs) -> Result<(), CliError> {
+    let bank = args.policies.apply(load_bank(args.state_in.as_deref())?);
+    let mut dry_run = bank.dry_run();
+    let (mut accepted, mut invalid) = (0, 0);
+    let mut rejected: BTreeMap<String, usize> = BTreeMap::new();
+    for path in &args.input.transactions_abs_paths {
+        let mut transactions = args.input.reader(path)?;
+        let mut printed = 0;
+        while let Some(transaction) = transactions.next() {
+            let tx = transaction.map_err(parse_error)?;
+            print_row_errors(path, transactions.row_errors(), &mut printed);
+            match dry_run.apply(&tx) {
+                Ok(()) => accepted += 1,
+                Err(err) if *err.current_context() == TxError::Storage => {
+                    log::error!("\n{err:?}");
+                    return Err(CliError::internal("Cannot read the bank state"));
+                }
+                Err(err) => {
+                    let reason = err.current_context().to_string();
+                    println!("{}:{}: {reason}", path.display(), transactions.line());
+                    *rejected.entry(reason).or_default() += 1;
+                }
+            }
+        }
+        print_row_errors(path, transactions.row_errors(), &mut printed);
+        invalid += transactions.row_errors().len();
+    }
+
+    let changed = dry_run.account_changes().map_err(|err| {
         log::error!("\n{err:?}");
-        match err.current_context() {
-            BankError::InvalidInput => return Err("Invalid input!".to_owned()),
-            BankError::Other => return Err("Internal error!".to_owned()),
+        CliError::internal("Cannot read the bank state")
+    })?;
+    let rejected_count: usize = rejected.values().sum();
+    eprintln!(
+        "{accepted} transactions would be accepted, {rejected_count} rejected, \
+         {invalid} invalid records, {} accounts would change",
+        changed.len()
+    );
+    for (reason, count) in &rejected {
+        eprintln!("  {count} rejected: {reason}");
+    }
+    match invalid {
+        0 => Ok(()),
+        _ => Err(CliError::invalid_input(format!(
+            "Invalid input: {invalid} invalid records"
+        ))),
+    }
+}
+
+fn statement(args: &StatementArgs) -> Result<(), CliError> {
+    let mut bank = args
+        .policies
+        .apply(load_bank(args.state_in.as_deref())?)
+        .with_history();
+    for path in &args.input.transactions_abs_paths {
+        for transaction in args.input.reader(path)? {
+            let tx = transaction.map_err(parse_error)?;
+            if let Err(err) = bank.apply(&tx) {
+                if *err.current_context() == TxError::Storage {
+                    log::error!("\n{err:?}");
+                    return Err(CliError::internal("Cannot store the bank state"));
+                }
+            }
         }
     }
 
-    let mut writer = std::io::stdout().lock();
-    if let Err(err) = bank.output_accounts_report(&mut writer) {
+    let statement = bank.statement(args.client, args.from..=args.to);
+    report::write_report(
+        &statement,
+        args.output.report_format(),
+        std::io::stdout().lock(),
+    )
+    .map_err(|err| {
         log::error!("\n{err:?}");
-        return Err("Cannot export client report to CSV from input".to_owned());
+        CliError::internal("Cannot export the statement")
+    })
+}
+
+fn report(state_abs_path: &Path, audit: bool, output: &OutputArgs) -> Result<(), CliError> {
+    let bank = load_bank(Some(state_abs_path))?;
+    if !audit {
+        return output_report(&bank, output);
     }
+    report::write_report(
+        bank.audit_trail(),
+        output.report_format(),
+        std::io::stdout().lock(),
+    )
+    .map_err(|err| {
+        log::error!("\n{err:?}");
+        CliError::internal("Cannot export the audit trail")
+    })
+}
 
-    Ok(())
+fn unlock(
+    state_abs_path: &Path,
+    client: ClientId,
+    operator: &str,
+    reason: &str,
+) -> Result<(), CliError> {
+    let mut bank = load_bank(Some(state_abs_path))?;
+    bank.unlock_account(client, operator, reason)
+        .map_err(|err| {
+            log::error!("\n{err:?}");
+            match err.current_context() {
+                AdminError::Journal => CliError::internal("Cannot journal the unlock"),
+                AdminError::Storage => CliError::internal("Cannot store the bank state"),
+                context => {
+                    CliError::invalid_input(format!("Cannot unlock client {client}: {context}"))
+                }
+            }
+        })?;
+    save_bank(&mut bank, Some(state_abs_path), None)
+}
+
+fn replay(
+    journal_abs_path: &Path,
+    state_in_abs_path: Option<&Path>,
+    state_out_abs_path: Option<&Path>,
+    policies: &PolicyArgs,
+    output: &OutputArgs,
+) -> Result<(), CliError> {
+    // recovering would start a new journal
+    if !journal_abs_path.is_file() {
+        return Err(CliError::invalid_input(format!(
+            "Invalid journal: {journal_abs_path:?} is not a valid file"
+        )));
+    }
+    let mut bank = policies.apply(load_bank(state_in_abs_path)?);
+    let position = bank
+        .recover_journal(journal_abs_path, DEFAULT_SYNC_EVERY)
+        .map_err(journal_error)?;
+    eprintln!("Replayed the journal up to transaction {position}");
+
+    save_bank(&mut bank, state_out_abs_path, Some(journal_abs_path))?;
+    output_report(&bank, output)
+}
+
+fn run(cli: Cli) -> Result<(), CliError> {
+    match (cli.command, cli.process) {
+        (Some(Command::Process(args)), _) | (None, args) => process(&args),
+        (Some(Command::Validate(args)), _) => validate(&args),
+        (Some(Command::Statement(args)), _) => statement(&args),
+        (
+            Some(Command::Report {
+                state,
+                audit,
+                output,
+            }),
+            _,
+        ) => report(&state, audit, &output),
+        (
+            Some(Command::Unlock {
+                state,
+                client,
+                operator,
+                reason,
+            }),
+            _,
+        ) => unlock(&state, client, &operator, &reason),
+        (
+            Some(Command::Replay {
+                journal,
+                state_in,
+                state_out,
+                policies,
+                output,
+            }),
+            _,
+        ) => replay(
+            &journal,
+            state_in.as_deref(),
+            state_out.as_deref(),
+            &policies,
+            &output,
+        ),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = cli.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::from(err.exit_code)
+        }
+    }
 }
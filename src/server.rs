@@ -0,0 +1,142 @@
+use crate::{
+    bank::Bank,
+    transactions::{parse_csv_line, ParseTxError},
+};
+use error_stack::{Context, IntoReport, Result, ResultExt};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+pub mod http;
+
+/// Command which answers with the CSV report of all accounts
+pub const REPORT_COMMAND: &str = "report";
+
+/// Longest line a connection can send in bytes, longer lines are answered as invalid without
+/// being buffered
+pub const MAX_LINE_LEN: usize = 1024;
+
+#[derive(Debug)]
+pub enum ServerError {
+    Other,
+}
+
+impl Context for ServerError {}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Transaction server failed")
+    }
+}
+
+/// A bank shared by all connections
+pub type SharedBank = Arc<Mutex<Bank>>;
+
+/// Accept connections until the listener fails, every connection is handled concurrently
+///
+/// A connection sends one CSV record per line, without header row, and gets one response line
+/// per record: `accepted`, `rejected,<reason>` or `invalid,<reason>`. A header row and empty
+/// lines are ignored. Lines longer than [`MAX_LINE_LEN`] are answered with `invalid,<reason>`
+/// and dropped. The line `report` is answered with the CSV report of all accounts,
+/// followed by an empty line. Records of one connection are applied in order, records of
+/// different connections are interleaved as they arrive.
+pub async fn serve(listener: TcpListener, bank: SharedBank) -> Result<(), ServerError> {
+    loop {
+        let (stream, peer) = listener
+            .accept()
+            .await
+            .report()
+            .change_context(ServerError::Other)
+            .attach_printable("Failed to accept a connection")?;
+        log::info!("Connection from {peer}");
+        let bank = bank.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, bank).await {
+                log::warn!("Connection from {peer} failed\n{err:?}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(stream: TcpStream, bank: SharedBank) -> Result<(), ServerError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    while read_line(&mut reader, &mut line).await? > 0 {
+        let response = if line.len() > MAX_LINE_LEN && !line.ends_with(b"\n") {
+            skip_line(&mut reader).await?;
+            Some(format!(
+                "invalid,line is longer than {MAX_LINE_LEN} bytes\n"
+            ))
+        } else {
+            match std::str::from_utf8(&line) {
+                Ok(line) => respond(line, &bank)?,
+                Err(_) => Some("invalid,line is not UTF-8\n".to_owned()),
+            }
+        };
+        if let Some(response) = response {
+            writer
+                .write_all(response.as_bytes())
+                .await
+                .report()
+                .change_context(ServerError::Other)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read up to the end of the line, but not more than one byte past [`MAX_LINE_LEN`]
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+) -> Result<usize, ServerError> {
+    line.clear();
+    reader
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_until(b'\n', line)
+        .await
+        .report()
+        .change_context(ServerError::Other)
+}
+
+/// Drop the rest of an oversized line, one bounded chunk at a time
+async fn skip_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<(), ServerError> {
+    let mut chunk = Vec::new();
+    while read_line(reader, &mut chunk).await? > 0 && !chunk.ends_with(b"\n") {}
+    Ok(())
+}
+
+fn respond(line: &str, bank: &SharedBank) -> Result<Option<String>, ServerError> {
+    let line = line.trim();
+    let first_field = line.split(',').next().unwrap_or_default().trim();
+    if line.is_empty() || first_field == "type" {
+        return Ok(None);
+    }
+
+    if line == REPORT_COMMAND {
+        let mut report = Vec::new();
+        let bank = bank.lock().expect("Bank is not poisoned");
+        bank.output_accounts_report(&mut report)
+            .change_context(ServerError::Other)?;
+        report.push(b'\n');
+        return Ok(Some(
+            String::from_utf8(report).expect("CSV report is UTF-8"),
+        ));
+    }
+    let response = match parse_csv_line(line) {
+        Ok(tx) => match bank.lock().expect("Bank is not poisoned").apply(&tx) {
+            Ok(()) => "accepted\n".to_owned(),
+            Err(err) => format!("rejected,{}\n", err.current_context()),
+        },
+        Err(err) => match err.current_context() {
+            ParseTxError::InvalidInput(msg) => format!("invalid,{msg}\n"),
+            ParseTxError::Other => "invalid,internal error\n".to_owned(),
+        },
+    };
+    Ok(Some(response))
+}
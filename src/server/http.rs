@@ -0,0 +1,154 @@
+use super::{ServerError, SharedBank};
+use crate::{
+    amount::Amount,
+    bank::{AccountReport, ClientId, TxError, TxState},
+    transactions::{parse_json_transaction, Kind, ParseTxError, TxId},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use error_stack::{IntoReport, Result, ResultExt};
+use serde::Serialize;
+use tokio::net::TcpListener;
+
+/// Response to a submitted transaction
+#[derive(Debug, Serialize)]
+struct Outcome {
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// Dispute state of an accepted deposit or withdrawal
+#[derive(Debug, Serialize)]
+struct TxStatus {
+    tx: TxId,
+    client: ClientId,
+    #[serde(rename = "type")]
+    kind: Kind,
+    amount: Amount,
+    state: TxState,
+}
+
+/// Routes of the HTTP API, all requests and responses are JSON
+///
+/// - `POST /transactions` applies a transaction with the same fields as a CSV record
+/// - `GET /accounts` returns the report of all accounts
+/// - `GET /accounts/{client}` returns the report of one account
+/// - `GET /transactions/{tx}` returns the dispute state of an accepted deposit or withdrawal
+pub fn router(bank: SharedBank) -> Router {
+    Router::new()
+        .route("/transactions", post(submit_transaction))
+        .route("/transactions/{tx}", get(transaction_status))
+        .route("/accounts", get(accounts))
+        .route("/accounts/{client}", get(account))
+        .with_state(bank)
+}
+
+/// Answer HTTP requests until the listener fails
+pub async fn serve(listener: TcpListener, bank: SharedBank) -> Result<(), ServerError> {
+    axum::serve(listener, router(bank))
+        .await
+        .report()
+        .change_context(ServerError::Other)
+}
+
+async fn submit_transaction(
+    State(bank): State<SharedBank>,
+    body: String,
+) -> (StatusCode, Json<Outcome>) {
+    let tx = match parse_json_transaction(&body) {
+        Ok(tx) => tx,
+        Err(err) => {
+            let reason = match err.current_context() {
+                ParseTxError::InvalidInput(msg) => msg.clone(),
+                ParseTxError::Other => "internal error".to_owned(),
+            };
+            let outcome = Outcome {
+                outcome: "invalid",
+                reason: Some(reason),
+            };
+            return (StatusCode::BAD_REQUEST, Json(outcome));
+        }
+    };
+
+    let result = bank.lock().expect("Bank is not poisoned").apply(&tx);
+    match result {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(Outcome {
+                outcome: "accepted",
+                reason: None,
+            }),
+        ),
+        Err(err) => {
+            let status = match err.current_context() {
+                TxError::Journal | TxError::Storage => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            let outcome = Outcome {
+                outcome: "rejected",
+                reason: Some(err.current_context().to_string()),
+            };
+            (status, Json(outcome))
+        }
+    }
+}
+
+async fn accounts(
+    State(bank): State<SharedBank>,
+) -> std::result::Result<Json<Vec<AccountReport>>, StatusCode> {
+    let reports = bank
+        .lock()
+        .expect("Bank is not poisoned")
+        .try_get_accounts_report();
+    reports.map(Json).map_err(|err| {
+        log::error!("\n{err:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn account(
+    State(bank): State<SharedBank>,
+    Path(client): Path<ClientId>,
+) -> std::result::Result<Json<AccountReport>, StatusCode> {
+    let report = bank
+        .lock()
+        .expect("Bank is not poisoned")
+        .try_get_account_report(client);
+    match report {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            log::error!("\n{err:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn transaction_status(
+    State(bank): State<SharedBank>,
+    Path(tx): Path<TxId>,
+) -> std::result::Result<Json<TxStatus>, StatusCode> {
+    let disputable_tx = bank
+        .lock()
+        .expect("Bank is not poisoned")
+        .try_get_disputable_tx(tx);
+    match disputable_tx {
+        Ok(Some(disputable_tx)) => Ok(Json(TxStatus {
+            tx,
+            client: disputable_tx.client,
+            kind: disputable_tx.kind,
+            amount: disputable_tx.amount,
+            state: disputable_tx.state,
+        })),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            log::error!("\n{err:?}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -1,9 +1,18 @@
+use crate::amount::Amount;
 use error_stack::{Context, IntoReport, Report, Result, ResultExt};
-use serde::Deserialize;
-use std::fmt;
-use std::path::PathBuf;
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    str::FromStr,
+};
 
-#[derive(Debug, Deserialize, PartialEq)]
+mod json;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum Kind {
     Deposit,
@@ -11,21 +20,24 @@ pub enum Kind {
     Dispute,
     Resolve,
     Chargeback,
+    Fee,
+    Interest,
+    Adjustment,
 }
 
 pub type TxId = u32;
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Transaction {
     #[serde(rename = "type")]
     pub kind: Kind,
     pub client: u16,
     pub tx: TxId,
-    pub amount: Option<f64>,
+    pub amount: Option<Amount>,
 }
 
 impl Transaction {
-    pub fn new(kind: Kind, client: u16, tx: TxId, amount: Option<f64>) -> Transaction {
+    pub fn new(kind: Kind, client: u16, tx: TxId, amount: Option<Amount>) -> Transaction {
         Transaction {
             kind,
             client,
@@ -35,7 +47,7 @@ pub fn new(kind: Kind, client: u16, tx: TxId, amount: Option<f64>) -> Transactio
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParseTxError {
     InvalidInput(String),
     Other,
@@ -49,56 +61,386 @@ fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+/// Whether the transaction moves funds under an id of its own, instead of referring to an earlier
+/// transaction by its id
+pub fn has_own_id(tx: &Transaction) -> bool {
+    matches!(
+        tx.kind,
+        Kind::Deposit | Kind::Withdrawal | Kind::Fee | Kind::Interest
+    )
+}
+
+/// Whether the transaction can be disputed and corrected by an adjustment later on
+///
+/// Interest is credited by the bank itself, so a client has nothing to dispute.
 pub fn is_disputable(tx: &Transaction) -> bool {
-    tx.kind == Kind::Deposit || tx.kind == Kind::Withdrawal
+    matches!(tx.kind, Kind::Deposit | Kind::Withdrawal | Kind::Fee)
 }
 
 fn validate_optional_field(transaction: &Transaction) -> Result<(), ParseTxError> {
-    if is_disputable(transaction) {
-        if transaction.amount.is_none() {
-            return Err(Report::new(ParseTxError::InvalidInput(format!(
-                "{:?} transactions must contain an amount",
-                transaction.kind
-            ))));
+    let reason = match (transaction.kind, transaction.amount) {
+        (Kind::Dispute | Kind::Resolve | Kind::Chargeback, None) => return Ok(()),
+        (Kind::Dispute | Kind::Resolve | Kind::Chargeback, Some(_)) => "cannot contain an amount",
+        (_, None) => "must contain an amount",
+        (Kind::Deposit | Kind::Withdrawal | Kind::Fee | Kind::Interest, Some(amount))
+            if amount <= Amount::ZERO =>
+        {
+            "must contain a positive amount"
+        }
+        (Kind::Adjustment, Some(amount)) if amount == Amount::ZERO => {
+            "cannot contain a zero amount"
+        }
+        (_, Some(_)) => return Ok(()),
+    };
+    Err(Report::new(ParseTxError::InvalidInput(format!(
+        "{:?} transactions {reason}",
+        transaction.kind
+    ))))
+}
+
+/// How invalid records are handled while reading transactions
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    /// Every invalid record is returned as an error
+    #[default]
+    Strict,
+    /// Invalid records are skipped and collected as row errors
+    Lenient,
+}
+
+/// A record which was skipped in lenient mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub line: u64,
+    pub error: ParseTxError,
+}
+
+/// Format of the transaction records in the input
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    /// CSV data which includes a header row
+    #[default]
+    Csv,
+    /// A single JSON array of transaction objects
+    Json,
+    /// One JSON transaction object per line
+    JsonLines,
+}
+
+impl InputFormat {
+    /// Format matching the extension of a file, CSV for any other extension and for std in
+    ///
+    /// The `.gz` extension of a compressed file is ignored.
+    pub fn from_path(transactions_abs_path: &Path) -> InputFormat {
+        let path = match transactions_abs_path.extension() {
+            Some(extension) if extension == "gz" => Path::new(
+                transactions_abs_path
+                    .file_stem()
+                    .unwrap_or(transactions_abs_path.as_os_str()),
+            ),
+            _ => transactions_abs_path,
+        };
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => InputFormat::Json,
+            Some("jsonl" | "ndjson") => InputFormat::JsonLines,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> std::result::Result<InputFormat, String> {
+        match format {
+            "csv" => Ok(InputFormat::Csv),
+            "json" => Ok(InputFormat::Json),
+            "jsonl" | "ndjson" => Ok(InputFormat::JsonLines),
+            _ => Err(format!("Unknown input format {format}")),
+        }
+    }
+}
+
+/// CSV records which are deserialized with the names of the header row
+struct CsvRecords<R: Read> {
+    reader: csv::Reader<R>,
+    headers: csv::StringRecord,
+    record: csv::StringRecord,
+}
+
+impl<R: Read> CsvRecords<R> {
+    fn new(input: R) -> Result<CsvRecords<R>, ParseTxError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(input);
+
+        let headers = reader
+            .headers()
+            .report()
+            .attach_printable("has no valid header row")
+            .change_context(ParseTxError::InvalidInput(
+                "CSV parser cannot be built".to_owned(),
+            ))?
+            .clone();
+
+        Ok(CsvRecords {
+            reader,
+            headers,
+            record: csv::StringRecord::new(),
+        })
+    }
+
+    fn line(&self) -> u64 {
+        self.record.position().map_or(0, |position| position.line())
+    }
+
+    fn parse_record(&self) -> Result<Transaction, ParseTxError> {
+        let line_nbr = self.line();
+        let transaction: Transaction = self
+            .record
+            .deserialize(Some(&self.headers))
+            .report()
+            .attach_printable(format!("has an invalid transaction on line {line_nbr}"))
+            .change_context(ParseTxError::InvalidInput(
+                "record cannot be parsed".to_owned(),
+            ))?;
+
+        validate_optional_field(&transaction)
+            .attach_printable(format!("has an invalid transaction on line {line_nbr}"))?;
+        Ok(transaction)
+    }
+
+    fn read_next(&mut self) -> Option<Result<Transaction, ParseTxError>> {
+        match self.reader.read_record(&mut self.record) {
+            Ok(true) => Some(self.parse_record()),
+            Ok(false) => None,
+            Err(err) => {
+                let context = if err.is_io_error() {
+                    ParseTxError::Other
+                } else {
+                    ParseTxError::InvalidInput("record cannot be read".to_owned())
+                };
+                Some(
+                    Err(err)
+                        .report()
+                        .attach_printable(format!(
+                            "has an unreadable record after line {}",
+                            self.line()
+                        ))
+                        .change_context(context),
+                )
+            }
         }
+    }
+}
+
+enum Records<R: Read> {
+    Csv(CsvRecords<R>),
+    Json(json::JsonArrayRecords),
+    JsonLines(json::JsonLinesRecords<R>),
+}
+
+/// Streaming reader of 'Transaction' records from CSV, JSON or JSON Lines data
+///
+/// Records are parsed one at a time, so memory use does not grow with the size of the input.
+/// Only a JSON array is read completely before its records are parsed.
+pub struct TransactionReader<R: Read> {
+    records: Records<R>,
+    mode: ParseMode,
+    row_errors: Vec<RowError>,
+}
+
+/// Path which stands for std in
+pub const STDIN_PATH: &str = "-";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Open a file, or std in for `-`, and decompress it when it is gzip compressed
+pub fn open_input(transactions_abs_path: &Path) -> Result<Box<dyn Read>, ParseTxError> {
+    let input: Box<dyn Read> = if transactions_abs_path == Path::new(STDIN_PATH) {
+        Box::new(std::io::stdin())
+    } else {
+        let file = File::open(transactions_abs_path)
+            .report()
+            .attach_printable(format!("{transactions_abs_path:?} is not a valid file"))
+            .change_context(ParseTxError::InvalidInput(
+                "transaction parser cannot be built".to_owned(),
+            ))?;
+        Box::new(file)
+    };
+
+    let mut input = BufReader::new(input);
+    let compressed = transactions_abs_path.extension() == Some("gz".as_ref())
+        || input
+            .fill_buf()
+            .report()
+            .attach_printable(format!("{transactions_abs_path:?} cannot be read"))
+            .change_context(ParseTxError::Other)?
+            .starts_with(&GZIP_MAGIC);
+    if compressed {
+        Ok(Box::new(MultiGzDecoder::new(input)))
     } else {
-        if transaction.amount.is_some() {
-            return Err(Report::new(ParseTxError::InvalidInput(format!(
-                "{:?} transactions cannot contain an amount",
-                transaction.kind
-            ))));
+        Ok(Box::new(input))
+    }
+}
+
+impl TransactionReader<Box<dyn Read>> {
+    /// Open a file, or std in for `-`, in the format matching its extension
+    pub fn from_path(
+        transactions_abs_path: &Path,
+    ) -> Result<TransactionReader<Box<dyn Read>>, ParseTxError> {
+        TransactionReader::from_path_with_format(
+            transactions_abs_path,
+            InputFormat::from_path(transactions_abs_path),
+        )
+    }
+
+    pub fn from_path_with_format(
+        transactions_abs_path: &Path,
+        format: InputFormat,
+    ) -> Result<TransactionReader<Box<dyn Read>>, ParseTxError> {
+        TransactionReader::from_reader_with_format(open_input(transactions_abs_path)?, format)
+    }
+}
+
+impl<R: Read> TransactionReader<R> {
+    /// Read CSV data which includes a header row
+    pub fn from_reader(input: R) -> Result<TransactionReader<R>, ParseTxError> {
+        TransactionReader::from_reader_with_format(input, InputFormat::Csv)
+    }
+
+    pub fn from_reader_with_format(
+        input: R,
+        format: InputFormat,
+    ) -> Result<TransactionReader<R>, ParseTxError> {
+        let records = match format {
+            InputFormat::Csv => Records::Csv(CsvRecords::new(input)?),
+            InputFormat::Json => Records::Json(json::JsonArrayRecords::new(input)?),
+            InputFormat::JsonLines => Records::JsonLines(json::JsonLinesRecords::new(input)),
+        };
+        Ok(TransactionReader {
+            records,
+            mode: ParseMode::default(),
+            row_errors: Vec::new(),
+        })
+    }
+
+    pub fn with_mode(mut self, mode: ParseMode) -> TransactionReader<R> {
+        self.mode = mode;
+        self
+    }
+
+    /// Records skipped so far in lenient mode, in input order
+    pub fn row_errors(&self) -> &[RowError] {
+        &self.row_errors
+    }
+
+    /// Line number of the record which was read last
+    pub fn line(&self) -> u64 {
+        match &self.records {
+            Records::Csv(records) => records.line(),
+            Records::Json(records) => records.line(),
+            Records::JsonLines(records) => records.line(),
+        }
+    }
+
+    fn read_next(&mut self) -> Option<Result<Transaction, ParseTxError>> {
+        match &mut self.records {
+            Records::Csv(records) => records.read_next(),
+            Records::Json(records) => records.read_next(),
+            Records::JsonLines(records) => records.read_next(),
         }
     }
-    Ok(())
 }
 
-/// Read 'Transaction' records from a CSV file which includes a header row
-pub fn parse_transactions(
-    transactions_abs_path: &PathBuf,
-) -> Result<Vec<Transaction>, ParseTxError> {
-    let mut transactions = Vec::new();
+impl<R: Read> Iterator for TransactionReader<R> {
+    type Item = Result<Transaction, ParseTxError>;
+
+    /// In lenient mode, invalid input is skipped until a valid record or a failure which is not
+    /// caused by the input itself is found
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.read_next()? {
+                Err(err)
+                    if self.mode == ParseMode::Lenient
+                        && matches!(err.current_context(), ParseTxError::InvalidInput(_)) =>
+                {
+                    log::warn!("Skipping invalid record\n{err:?}");
+                    self.row_errors.push(RowError {
+                        line: self.line(),
+                        error: err.current_context().clone(),
+                    });
+                }
+                result => return Some(result),
+            }
+        }
+    }
+}
 
+/// Parse a single CSV record without header row, with the fields in the order of the header row
+/// of CSV files: `type, client, tx, amount`
+pub fn parse_csv_line(line: &str) -> Result<Transaction, ParseTxError> {
     let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
         .trim(csv::Trim::All)
         .flexible(true)
-        .from_path(transactions_abs_path)
+        .from_reader(line.as_bytes());
+    let headers = csv::StringRecord::from(vec!["type", "client", "tx", "amount"]);
+    let mut record = csv::StringRecord::new();
+    match reader.read_record(&mut record) {
+        Ok(true) => (),
+        Ok(false) => {
+            return Err(Report::new(ParseTxError::InvalidInput(
+                "record is empty".to_owned(),
+            )))
+        }
+        Err(err) => {
+            return Err(err).report().change_context(ParseTxError::InvalidInput(
+                "record cannot be read".to_owned(),
+            ))
+        }
+    }
+    let transaction: Transaction = record
+        .deserialize(Some(&headers))
         .report()
-        .attach_printable(format!("{transactions_abs_path:?} is not a valid file"))
+        .attach_printable(format!("{line:?} is an invalid transaction"))
         .change_context(ParseTxError::InvalidInput(
-            "CSV parser cannot be built".to_owned(),
+            "record cannot be parsed".to_owned(),
         ))?;
+    validate_optional_field(&transaction)?;
+    Ok(transaction)
+}
 
-    for (idx, result) in reader.deserialize().into_iter().enumerate() {
-        let line_nbr = idx + 1; // 1 header + starting from 1
-        let record: Transaction = result
+/// Convert a JSON object into a 'Transaction', a numeric amount is read from its exact text so
+/// no precision is lost
+pub fn transaction_from_json(mut value: serde_json::Value) -> Result<Transaction, ParseTxError> {
+    if let Some(amount) = value.get_mut("amount") {
+        if let serde_json::Value::Number(number) = amount {
+            *amount = serde_json::Value::String(number.to_string());
+        }
+    }
+    let transaction: Transaction =
+        serde_json::from_value(value)
             .report()
-            .attach_printable(format!("has an invalid transaction on line {line_nbr}"))
             .change_context(ParseTxError::InvalidInput(
                 "record cannot be parsed".to_owned(),
             ))?;
+    validate_optional_field(&transaction)?;
+    Ok(transaction)
+}
 
-        validate_optional_field(&record)?;
-        transactions.push(record);
-    }
-    Ok(transactions)
+/// Parse a single JSON object with the same fields as the header row of CSV files
+pub fn parse_json_transaction(json: &str) -> Result<Transaction, ParseTxError> {
+    let value = serde_json::from_str(json)
+        .report()
+        .change_context(ParseTxError::InvalidInput(
+            "record is not valid JSON".to_owned(),
+        ))?;
+    transaction_from_json(value)
+}
+
+/// Read all 'Transaction' records from a file in the format matching its extension
+pub fn parse_transactions(transactions_abs_path: &Path) -> Result<Vec<Transaction>, ParseTxError> {
+    TransactionReader::from_path(transactions_abs_path)?.collect()
 }
@@ -0,0 +1,187 @@
+use super::{parse_json_transaction, transaction_from_json, ParseTxError, Transaction};
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+
+/// One JSON transaction object per line, empty lines are skipped
+pub(super) struct JsonLinesRecords<R: Read> {
+    reader: BufReader<R>,
+    buffer: String,
+    line: u64,
+}
+
+impl<R: Read> JsonLinesRecords<R> {
+    pub(super) fn new(input: R) -> JsonLinesRecords<R> {
+        JsonLinesRecords {
+            reader: BufReader::new(input),
+            buffer: String::new(),
+            line: 0,
+        }
+    }
+
+    pub(super) fn line(&self) -> u64 {
+        self.line
+    }
+
+    pub(super) fn read_next(&mut self) -> Option<Result<Transaction, ParseTxError>> {
+        loop {
+            self.buffer.clear();
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    self.line += 1;
+                    let record = self.buffer.trim();
+                    if !record.is_empty() {
+                        return Some(parse_json_transaction(record).attach_printable(format!(
+                            "has an invalid transaction on line {}",
+                            self.line
+                        )));
+                    }
+                }
+                Err(err) => {
+                    self.line += 1;
+                    let context = if err.kind() == ErrorKind::InvalidData {
+                        ParseTxError::InvalidInput("record cannot be read".to_owned())
+                    } else {
+                        ParseTxError::Other
+                    };
+                    return Some(
+                        Err(err)
+                            .report()
+                            .attach_printable(format!(
+                                "has an unreadable record on line {}",
+                                self.line
+                            ))
+                            .change_context(context),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ArrayState {
+    Start,
+    FirstElement,
+    NextElement,
+    End,
+}
+
+/// A single JSON array of transaction objects
+///
+/// The whole array is read at once, the elements are only parsed one at a time. An element
+/// with invalid fields can be skipped, invalid JSON ends the array.
+pub(super) struct JsonArrayRecords {
+    text: String,
+    offset: usize,
+    /// Line of the current offset
+    offset_line: u64,
+    /// Line where the element which was read last starts
+    line: u64,
+    state: ArrayState,
+}
+
+fn invalid_array(line: u64) -> Report<ParseTxError> {
+    Report::new(ParseTxError::InvalidInput(
+        "input is not a valid JSON array".to_owned(),
+    ))
+    .attach_printable(format!("has invalid JSON on line {line}"))
+}
+
+impl JsonArrayRecords {
+    pub(super) fn new<R: Read>(mut input: R) -> Result<JsonArrayRecords, ParseTxError> {
+        let mut text = String::new();
+        input.read_to_string(&mut text).report().map_err(|err| {
+            let context = if err.current_context().kind() == ErrorKind::InvalidData {
+                ParseTxError::InvalidInput("input is not valid UTF-8".to_owned())
+            } else {
+                ParseTxError::Other
+            };
+            err.change_context(context)
+        })?;
+        Ok(JsonArrayRecords {
+            text,
+            offset: 0,
+            offset_line: 1,
+            line: 0,
+            state: ArrayState::Start,
+        })
+    }
+
+    pub(super) fn line(&self) -> u64 {
+        self.line
+    }
+
+    fn advance(&mut self, len: usize) {
+        let skipped = &self.text[self.offset..self.offset + len];
+        self.offset_line += skipped.bytes().filter(|byte| *byte == b'\n').count() as u64;
+        self.offset += len;
+    }
+
+    /// Skip whitespace and return the next character without consuming it
+    fn peek(&mut self) -> Option<u8> {
+        let rest = &self.text[self.offset..];
+        let whitespace = rest.len() - rest.trim_start().len();
+        self.advance(whitespace);
+        self.text.as_bytes().get(self.offset).copied()
+    }
+
+    pub(super) fn read_next(&mut self) -> Option<Result<Transaction, ParseTxError>> {
+        let result = self.read_element();
+        if let Some(Err(err)) = &result {
+            if *err.current_context()
+                == ParseTxError::InvalidInput("input is not a valid JSON array".to_owned())
+            {
+                // the next element cannot be found any more
+                self.state = ArrayState::End;
+            }
+        }
+        result
+    }
+
+    fn read_element(&mut self) -> Option<Result<Transaction, ParseTxError>> {
+        loop {
+            match (self.state, self.peek()) {
+                (ArrayState::End, _) => return None,
+                (ArrayState::Start, Some(b'[')) => {
+                    self.advance(1);
+                    self.state = ArrayState::FirstElement;
+                }
+                (ArrayState::FirstElement | ArrayState::NextElement, Some(b']')) => {
+                    self.advance(1);
+                    self.state = ArrayState::End;
+                    if self.peek().is_some() {
+                        self.line = self.offset_line;
+                        return Some(Err(invalid_array(self.line)));
+                    }
+                }
+                (ArrayState::NextElement, Some(b',')) => {
+                    self.advance(1);
+                    self.state = ArrayState::FirstElement;
+                }
+                (ArrayState::FirstElement, Some(_)) => {
+                    self.state = ArrayState::NextElement;
+                    return Some(self.parse_element());
+                }
+                _ => {
+                    self.line = self.offset_line;
+                    return Some(Err(invalid_array(self.line)));
+                }
+            }
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<Transaction, ParseTxError> {
+        self.line = self.offset_line;
+        let mut values = serde_json::Deserializer::from_str(&self.text[self.offset..])
+            .into_iter::<serde_json::Value>();
+        let value = match values.next() {
+            Some(Ok(value)) => value,
+            _ => return Err(invalid_array(self.line)),
+        };
+        let len = values.byte_offset();
+        self.advance(len);
+        transaction_from_json(value)
+            .attach_printable(format!("has an invalid transaction on line {}", self.line))
+    }
+}
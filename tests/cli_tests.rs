@@ -0,0 +1,459 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::amount;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process::{Command, Output, Stdio};
+    use tx_engine::{
+        bank::journal::DEFAULT_SYNC_EVERY,
+        bank::Bank,
+        transactions::{Kind, Transaction},
+    };
+
+    fn input_file(file_name: &str) -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("input_data");
+        path.push(file_name);
+        path
+    }
+
+    fn tx_engine(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_tx_engine"))
+            .args(args)
+            .output()
+            .expect("Running tx_engine failed!")
+    }
+
+    fn tx_engine_with_stdin(args: &[&str], stdin: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tx_engine"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Running tx_engine failed!");
+        child
+            .stdin
+            .take()
+            .expect("Piped std in")
+            .write_all(stdin.as_bytes())
+            .expect("Writing std in failed!");
+        child.wait_with_output().expect("Running tx_engine failed!")
+    }
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8(output.stdout.clone()).expect("UTF-8 output")
+    }
+
+    #[test]
+    fn test_transactions_are_processed_without_a_command() {
+        let csv_file = input_file("basic_transactions.csv");
+        let csv_file = csv_file.to_str().expect("UTF-8 path");
+
+        let output = tx_engine(&[csv_file]);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(
+            stdout(&output),
+            "client,available,held,total,locked\n\
+             1,1.5000,0.0000,1.5000,false\n\
+             2,2.0000,0.0000,2.0000,false\n"
+        );
+        assert_eq!(stdout(&tx_engine(&["process", csv_file])), stdout(&output));
+    }
+
+    #[test]
+    fn test_std_in_and_files_form_one_stream() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let rejected_file = dir.path().join("rejected.csv");
+        let day2 = dir.path().join("day2.csv");
+        std::fs::write(
+            &day2,
+            "type, client, tx, amount\n\
+             withdrawal, 1, 3, 5.0\n\
+             deposit, 2, 1, 2.0\n\
+             deposit, 2, 4, 2.0\n",
+        )
+        .expect("Writing the input failed");
+        let day2 = day2.to_str().expect("UTF-8 path");
+
+        let output = tx_engine_with_stdin(
+            &[
+                "--rejected",
+                rejected_file.to_str().expect("UTF-8 path"),
+                "-",
+                day2,
+            ],
+            "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,5.0\n",
+        );
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(
+            stdout(&output),
+            "client,available,held,total,locked\n\
+             1,1.0000,0.0000,1.0000,false\n\
+             2,2.0000,0.0000,2.0000,false\n"
+        );
+        // the id of the deposit read from std in is used in the file as well
+        assert_eq!(
+            std::fs::read_to_string(&rejected_file).expect("Reading rejections failed"),
+            format!(
+                "input,line,type,client,tx,amount,reason\n\
+                 -,3,withdrawal,1,2,5.0000,Insufficient available funds\n\
+                 {day2},2,withdrawal,1,3,5.0000,Insufficient available funds\n\
+                 {day2},3,deposit,2,1,2.0000,Transaction id is already used\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_exit_code_tells_invalid_input_apart() {
+        let invalid_file = input_file("partially_invalid_transactions.csv");
+        let invalid_file = invalid_file.to_str().expect("UTF-8 path");
+
+        assert_eq!(tx_engine(&[invalid_file]).status.code(), Some(65));
+        assert_eq!(
+            tx_engine(&["--unknown", invalid_file]).status.code(),
+            Some(2)
+        );
+
+        let output = tx_engine(&["validate", "--lenient", invalid_file]);
+        assert_eq!(output.status.code(), Some(65));
+        assert_eq!(
+            stdout(&output)
+                .lines()
+                .map(|line| line.rsplit(':').nth(1).expect("Line number"))
+                .collect::<Vec<_>>(),
+            vec!["3", "5", "6"]
+        );
+
+        let valid_file = input_file("basic_transactions.csv");
+        let output = tx_engine(&["validate", valid_file.to_str().expect("UTF-8 path")]);
+        // a rejected transaction does not make the input invalid
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(
+            stdout(&output),
+            format!(
+                "{}:6: Insufficient available funds\n",
+                valid_file.to_str().expect("UTF-8 path")
+            )
+        );
+    }
+
+    #[test]
+    fn test_report_is_printed_from_a_saved_state() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let state_file = dir.path().join("bank.state");
+        let state_file = state_file.to_str().expect("UTF-8 path");
+        let csv_file = input_file("deposit_disputed.csv");
+
+        let processed = tx_engine(&[
+            "process",
+            "--state-out",
+            state_file,
+            csv_file.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(processed.status.code(), Some(0));
+
+        let reported = tx_engine(&["report", "--state", state_file]);
+        assert_eq!(reported.status.code(), Some(0));
+        assert_eq!(stdout(&reported), stdout(&processed));
+    }
+
+    #[test]
+    fn test_validate_shows_rejections_without_changing_the_state() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let state_file = dir.path().join("bank.state");
+        let state_file = state_file.to_str().expect("UTF-8 path");
+        let csv_file = input_file("basic_transactions.csv");
+        let csv_file = csv_file.to_str().expect("UTF-8 path");
+
+        let processed = tx_engine(&["process", "--state-out", state_file, csv_file]);
+        assert_eq!(processed.status.code(), Some(0));
+
+        // every transaction id is used already
+        let validated = tx_engine(&["validate", "--state-in", state_file, csv_file]);
+        assert_eq!(validated.status.code(), Some(0));
+        assert_eq!(
+            stdout(&validated)
+                .lines()
+                .map(|line| line.rsplit(": ").next().expect("Reason"))
+                .collect::<Vec<_>>(),
+            vec!["Transaction id is already used"; 5]
+        );
+
+        let reported = tx_engine(&["report", "--state", state_file]);
+        assert_eq!(stdout(&reported), stdout(&processed));
+    }
+
+    #[test]
+    fn test_a_journal_of_a_finished_run_is_not_resumed() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+        let journal_file = journal_file.to_str().expect("UTF-8 path");
+        let day1 = input_file("basic_transactions.csv");
+        let day2 = dir.path().join("day2.csv");
+        std::fs::write(
+            &day2,
+            "type, client, tx, amount\n\
+             deposit, 7, 6, 2.0\n\
+             deposit, 7, 7, 1.0\n\
+             deposit, 1, 8, 1.0\n\
+             withdrawal, 7, 9, 0.5\n\
+             deposit, 2, 10, 1.0\n",
+        )
+        .expect("Writing the input failed");
+
+        let processed = tx_engine(&[
+            "process",
+            "--journal",
+            journal_file,
+            day1.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(processed.status.code(), Some(0));
+
+        let processed = tx_engine(&[
+            "process",
+            "--journal",
+            journal_file,
+            day2.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(processed.status.code(), Some(0));
+        assert!(!String::from_utf8_lossy(&processed.stderr).contains("Resuming"));
+        // the second run continues from the journaled state of the first one
+        assert_eq!(
+            stdout(&processed),
+            "client,available,held,total,locked\n\
+             1,2.5000,0.0000,2.5000,false\n\
+             2,3.0000,0.0000,3.0000,false\n\
+             7,2.5000,0.0000,2.5000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_policies_are_chosen_per_run_and_replayed_from_the_journal() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let journal_file = dir.path().join("bank.journal");
+        let journal_file = journal_file.to_str().expect("UTF-8 path");
+        let csv_file = input_file("disputes_after_chargeback.csv");
+        let csv_file = csv_file.to_str().expect("UTF-8 path");
+
+        assert_eq!(
+            stdout(&tx_engine(&["process", csv_file])),
+            "client,available,held,total,locked\n\
+             1,0.0000,4.0000,4.0000,true\n"
+        );
+        // the dispute opened before the lock can still be resolved
+        let processed = tx_engine(&[
+            "process",
+            "--locked-account-policy",
+            "disputes-only",
+            "--journal",
+            journal_file,
+            csv_file,
+        ]);
+        assert_eq!(processed.status.code(), Some(0));
+        assert_eq!(
+            stdout(&processed),
+            "client,available,held,total,locked\n\
+             1,4.0000,0.0000,4.0000,true\n"
+        );
+
+        // the finished run is replayed with the policies it was journaled with
+        let replayed = tx_engine(&["replay", "--journal", journal_file]);
+        assert_eq!(replayed.status.code(), Some(0));
+        assert_eq!(stdout(&replayed), stdout(&processed));
+
+        let invalid = tx_engine(&["process", "--fee-policy", "waive", csv_file]);
+        assert_eq!(invalid.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&invalid.stderr).contains("Unknown fee policy waive"));
+    }
+
+    #[test]
+    fn test_a_store_dir_is_continued_by_the_next_run() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let store_dir = dir.path().join("bank");
+        let store_dir = store_dir.to_str().expect("UTF-8 path");
+        let day1 = input_file("basic_transactions.csv");
+        let day2 = dir.path().join("day2.csv");
+        std::fs::write(
+            &day2,
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 5.0\n\
+             deposit, 2, 6, 1.0\n",
+        )
+        .expect("Writing the input failed");
+
+        let processed = tx_engine(&[
+            "process",
+            "--store-dir",
+            store_dir,
+            day1.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(processed.status.code(), Some(0));
+        assert_eq!(
+            stdout(&processed),
+            stdout(&tx_engine(&[day1.to_str().expect("UTF-8 path")]))
+        );
+
+        // the id of the first deposit is still in use
+        let processed = tx_engine(&[
+            "process",
+            "--store-dir",
+            store_dir,
+            day2.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(processed.status.code(), Some(0));
+        assert_eq!(
+            stdout(&processed),
+            "client,available,held,total,locked\n\
+             1,1.5000,0.0000,1.5000,false\n\
+             2,3.0000,0.0000,3.0000,false\n"
+        );
+
+        let conflicting = tx_engine(&[
+            "process",
+            "--store-dir",
+            store_dir,
+            "--state-out",
+            "bank.state",
+            day2.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(conflicting.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_a_resumed_run_writes_every_rejection_once() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let csv_file = dir.path().join("transactions.csv");
+        std::fs::write(
+            &csv_file,
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 2.0\n\
+             withdrawal, 1, 2, 5.0\n\
+             bogus, 1, 3, 1.0\n\
+             deposit, 1, 1, 1.0\n\
+             deposit, 2, 4, 1.0\n\
+             withdrawal, 2, 5, 3.0\n\
+             bogus\n\
+             deposit, 2, 6, 1.0\n",
+        )
+        .expect("Writing the input failed");
+        let csv_file = csv_file.to_str().expect("UTF-8 path");
+        let rejected_file = dir.path().join("rejected.csv");
+        let journal_file = dir.path().join("bank.journal");
+        let args = [
+            "process",
+            "--lenient",
+            "--rejected",
+            rejected_file.to_str().expect("UTF-8 path"),
+            "--journal",
+            journal_file.to_str().expect("UTF-8 path"),
+            csv_file,
+        ];
+
+        let full = tx_engine(&args);
+        assert_eq!(full.status.code(), Some(0));
+        let full_rejections =
+            std::fs::read_to_string(&rejected_file).expect("Reading rejections failed");
+        assert_eq!(full_rejections.lines().count(), 6);
+
+        // the interrupted run wrote every rejection, but only journaled the first withdrawal,
+        // the duplicate deposit after it changed nothing
+        std::fs::remove_file(&journal_file).expect("Removing the journal failed");
+        let mut bank = Bank::new();
+        bank.recover_journal(&journal_file, DEFAULT_SYNC_EVERY)
+            .expect("Creating the journal failed");
+        bank.handle_transactions(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("2.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 2, Some(amount("5.0"))),
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))),
+        ]);
+        drop(bank);
+
+        let resumed = tx_engine(&args);
+        assert_eq!(resumed.status.code(), Some(0));
+        assert!(String::from_utf8_lossy(&resumed.stderr)
+            .contains("Resuming after 2 transactions from the journal"));
+        assert!(String::from_utf8_lossy(&resumed.stderr)
+            .contains("2 transactions accepted, 2 rejected, 2 invalid records skipped"));
+        assert_eq!(
+            std::fs::read_to_string(&rejected_file).expect("Reading rejections failed"),
+            full_rejections
+        );
+        assert_eq!(stdout(&resumed), stdout(&full));
+        assert!(!dir.path().join("rejected.csv.interrupted").exists());
+    }
+
+    #[test]
+    fn test_the_server_takes_the_same_policy_options() {
+        let output = Command::new(env!("CARGO_BIN_EXE_tx_server"))
+            .args(["--fee-policy", "waive"])
+            .output()
+            .expect("Running tx_server failed!");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown fee policy waive"));
+    }
+
+    #[test]
+    fn test_statement_lists_movements_between_positions() {
+        let csv_file = input_file("deposit_withdrawal_chargeback.csv");
+
+        let output = tx_engine(&[
+            "statement",
+            "--client",
+            "1",
+            "--from",
+            "2",
+            "--to",
+            "3",
+            csv_file.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(
+            stdout(&output),
+            "position,tx,type,amount,available,held,total\n\
+             2,2,withdrawal,4.0000,6.0000,0.0000,6.0000\n\
+             3,2,dispute,,10.0000,-4.0000,6.0000\n"
+        );
+    }
+
+    #[test]
+    fn test_unlocking_an_account_is_kept_in_the_audit_trail() {
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let state_file = dir.path().join("bank.state");
+        let state_file = state_file.to_str().expect("UTF-8 path");
+        let csv_file = input_file("deposit_chargeback.csv");
+
+        let processed = tx_engine(&[
+            "process",
+            "--state-out",
+            state_file,
+            csv_file.to_str().expect("UTF-8 path"),
+        ]);
+        assert_eq!(processed.status.code(), Some(0));
+
+        let unlock = [
+            "unlock",
+            "--state",
+            state_file,
+            "--client",
+            "1",
+            "--operator",
+            "ops-7",
+            "--reason",
+            "customer verified",
+        ];
+        assert_eq!(tx_engine(&unlock).status.code(), Some(0));
+        // the account is not locked any more
+        assert_eq!(tx_engine(&unlock).status.code(), Some(65));
+
+        let audited = tx_engine(&["report", "--state", state_file, "--audit"]);
+        assert_eq!(
+            stdout(&audited),
+            "client,action,tx,operator,reason\n\
+             1,locked,1,,chargeback\n\
+             1,unlocked,,ops-7,customer verified\n"
+        );
+    }
+}
@@ -0,0 +1,6 @@
+use tx_engine::amount::Amount;
+
+/// Parse an amount written in a test
+pub fn amount(s: &str) -> Amount {
+    s.parse().expect("Valid amount")
+}
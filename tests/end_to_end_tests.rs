@@ -1,7 +1,16 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
-    use tx_engine::{bank::AccountReport, bank::Bank, transactions};
+    use crate::common::amount;
+    use std::path::{Path, PathBuf};
+    use tx_engine::{
+        bank::rejections::{RejectedTx, RejectionWriter},
+        bank::sharded::ShardedBank,
+        bank::store::{FileAccountStore, FileTxStore},
+        bank::{AccountReport, Bank, DisputePolicy, LockedAccountPolicy, TxError, TxOutcome},
+        transactions,
+    };
 
     #[test]
     fn test_a_client_got_a_dispute_on_a_failed_withdrawal() {
@@ -13,10 +22,15 @@ fn test_a_client_got_a_dispute_on_a_failed_withdrawal() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
 
-        let expected = vec![AccountReport::new(1, 0.0, 0.0, 0.0, false)];
+        let expected = vec![AccountReport::new(
+            1,
+            amount("0.0"),
+            amount("0.0"),
+            amount("0.0"),
+            false,
+        )];
 
         assert_eq!(bank.get_accounts_report(), expected);
     }
@@ -31,10 +45,15 @@ fn test_a_client_got_a_resolve_on_a_failed_withdrawal() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
 
-        let expected = vec![AccountReport::new(1, 0.0, 0.0, 0.0, false)];
+        let expected = vec![AccountReport::new(
+            1,
+            amount("0.0"),
+            amount("0.0"),
+            amount("0.0"),
+            false,
+        )];
 
         assert_eq!(bank.get_accounts_report(), expected);
     }
@@ -49,10 +68,15 @@ fn test_a_client_got_a_chargeback_on_a_failed_withdrawal() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
 
-        let expected = vec![AccountReport::new(1, 0.0, 0.0, 0.0, false)];
+        let expected = vec![AccountReport::new(
+            1,
+            amount("0.0"),
+            amount("0.0"),
+            amount("0.0"),
+            false,
+        )];
 
         assert_eq!(bank.get_accounts_report(), expected);
     }
@@ -67,10 +91,15 @@ fn test_a_client_got_a_dispute_on_a_deposit() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
 
-        let expected = vec![AccountReport::new(1, 0.0, 4.0, 4.0, false)];
+        let expected = vec![AccountReport::new(
+            1,
+            amount("0.0"),
+            amount("4.0"),
+            amount("4.0"),
+            false,
+        )];
 
         assert_eq!(bank.get_accounts_report(), expected);
     }
@@ -85,10 +114,15 @@ fn test_a_client_got_a_resolve_on_a_deposit() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
 
-        let expected = vec![AccountReport::new(1, 4.0, 0.0, 4.0, false)];
+        let expected = vec![AccountReport::new(
+            1,
+            amount("4.0"),
+            amount("0.0"),
+            amount("4.0"),
+            false,
+        )];
 
         assert_eq!(bank.get_accounts_report(), expected);
     }
@@ -103,10 +137,15 @@ fn test_a_client_got_a_chargeback_on_a_deposit() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
 
-        let expected = vec![AccountReport::new(1, 0.0, 0.0, 0.0, true)];
+        let expected = vec![AccountReport::new(
+            1,
+            amount("0.0"),
+            amount("0.0"),
+            amount("0.0"),
+            true,
+        )];
 
         assert_eq!(bank.get_accounts_report(), expected);
     }
@@ -121,13 +160,12 @@ fn test_three_clients_have_their_correct_funds_after_handling_transactions() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
 
         let expected = vec![
-            AccountReport::new(1, 1.0, 0.0, 1.0, true),
-            AccountReport::new(2, 2.0, 0.0, 2.0, false),
-            AccountReport::new(3, 5.0, 0.0, 5.0, false),
+            AccountReport::new(1, amount("1.0"), amount("0.0"), amount("1.0"), true),
+            AccountReport::new(2, amount("2.0"), amount("0.0"), amount("2.0"), false),
+            AccountReport::new(3, amount("5.0"), amount("0.0"), amount("5.0"), false),
         ];
 
         assert_eq!(bank.get_accounts_report(), expected);
@@ -143,11 +181,417 @@ fn test_numbers_are_reported_with_4_digits_after_the_decimal() {
         let actual_transactions =
             transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
 
-        bank.handle_transactions(actual_transactions)
-            .expect("Handling transactions failed!");
+        bank.handle_transactions(actual_transactions);
+
+        // every record is rounded to 4 decimals on its own before it is applied, so 1.000010001,
+        // 2.000020001 and 3.000030001 add up to exactly 6.0000 instead of a rounded 6.000060003
+        let expected = vec![AccountReport::new(
+            1,
+            amount("6.0"),
+            amount("0.0"),
+            amount("6.0"),
+            false,
+        )];
+        assert_eq!(bank.get_accounts_report(), expected);
+
+        let mut output = Vec::new();
+        bank.output_accounts_report(&mut output)
+            .expect("Writing the report failed");
+        assert_eq!(
+            String::from_utf8(output).expect("UTF-8 output"),
+            "client,available,held,total,locked\n1,6.0000,0.0000,6.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn test_a_dispute_cannot_reference_a_later_transaction() {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("dispute_before_deposit.csv");
+
+        let mut bank = Bank::new();
+        let actual_transactions =
+            transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
+
+        bank.handle_transactions(actual_transactions);
 
-        let expected = vec![AccountReport::new(1, 6.0001, 0.0, 6.0001, false)];
+        let expected = vec![AccountReport::new(
+            1,
+            amount("4.0"),
+            amount("0.0"),
+            amount("4.0"),
+            false,
+        )];
 
         assert_eq!(bank.get_accounts_report(), expected);
     }
+
+    #[test]
+    fn test_a_locked_client_only_rejects_transactions_after_the_chargeback() {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("transactions_after_chargeback.csv");
+
+        let mut bank = Bank::new();
+        let actual_transactions =
+            transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
+
+        let outcomes = bank.handle_transactions(actual_transactions);
+
+        let locked = TxOutcome::Rejected(TxError::AccountLocked);
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                locked.clone(),
+                locked,
+                TxOutcome::Accepted,
+            ]
+        );
+
+        let expected = vec![
+            AccountReport::new(1, amount("4.0"), amount("0.0"), amount("4.0"), true),
+            AccountReport::new(2, amount("1.0"), amount("0.0"), amount("1.0"), false),
+        ];
+
+        assert_eq!(bank.get_accounts_report(), expected);
+    }
+
+    #[test]
+    fn test_a_locked_client_accepts_what_the_locked_account_policy_allows() {
+        let accepted = TxOutcome::Accepted;
+        let locked = TxOutcome::Rejected(TxError::AccountLocked);
+        for (policy, expected_outcomes, expected) in [
+            (
+                LockedAccountPolicy::RejectAll,
+                [locked.clone(), locked.clone(), locked.clone()],
+                AccountReport::new(1, amount("0.0"), amount("4.0"), amount("4.0"), true),
+            ),
+            (
+                LockedAccountPolicy::DepositsOnly,
+                [accepted.clone(), locked.clone(), locked.clone()],
+                AccountReport::new(1, amount("5.0"), amount("4.0"), amount("9.0"), true),
+            ),
+            (
+                LockedAccountPolicy::DisputesOnly,
+                [locked.clone(), locked.clone(), accepted.clone()],
+                AccountReport::new(1, amount("4.0"), amount("0.0"), amount("4.0"), true),
+            ),
+        ] {
+            let mut bank = Bank::new().with_locked_account_policy(policy);
+            let outcomes = handle_input_file(&mut bank, "disputes_after_chargeback.csv");
+
+            assert_eq!(outcomes[..5], vec![accepted.clone(); 5], "{policy:?}");
+            assert_eq!(outcomes[5..], expected_outcomes, "{policy:?}");
+            assert_eq!(bank.get_accounts_report(), vec![expected], "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_illegal_dispute_transitions_do_not_change_the_funds() {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("deposit_illegal_dispute_transitions.csv");
+
+        let mut bank = Bank::new();
+        let actual_transactions =
+            transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
+
+        bank.handle_transactions(actual_transactions);
+
+        let expected = vec![AccountReport::new(
+            1,
+            amount("6.0"),
+            amount("0.0"),
+            amount("6.0"),
+            false,
+        )];
+
+        assert_eq!(bank.get_accounts_report(), expected);
+    }
+
+    #[test]
+    fn test_rejected_transactions_are_written_with_their_line_and_reason() {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("multiple_client_transactions.csv");
+
+        let mut bank = Bank::new();
+        let mut output = Vec::new();
+        let mut rejections =
+            RejectionWriter::new(&mut output).expect("Creating the rejection writer failed!");
+        let mut transactions =
+            transactions::TransactionReader::from_path(&csv_file).expect("Opening file failed!");
+        while let Some(tx) = transactions.next() {
+            let tx = tx.expect("Parsing transaction failed!");
+            if let Err(err) = bank.apply(&tx) {
+                let rejected = RejectedTx::new(
+                    Path::new("multiple_client_transactions.csv"),
+                    transactions.line(),
+                    &tx,
+                    err.current_context(),
+                );
+                rejections
+                    .write(&rejected)
+                    .expect("Writing rejected transaction failed!");
+            }
+        }
+        rejections.flush().expect("Flushing rejections failed!");
+        drop(rejections);
+
+        let expected = "input,line,type,client,tx,amount,reason\n\
+                        multiple_client_transactions.csv,8,withdrawal,1,4,1.5000,Insufficient available funds\n\
+                        multiple_client_transactions.csv,9,withdrawal,2,5,3.0000,Insufficient available funds\n";
+
+        assert_eq!(String::from_utf8(output).expect("Invalid UTF-8"), expected);
+    }
+
+    #[test]
+    fn test_a_client_cannot_dispute_transactions_of_another_client() {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("cross_client_dispute.csv");
+
+        let mut bank = Bank::new();
+        let actual_transactions =
+            transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
+
+        let outcomes = bank.handle_transactions(actual_transactions);
+
+        let mismatch = TxOutcome::Rejected(TxError::ClientMismatch { owner: 1 });
+        assert_eq!(
+            outcomes,
+            vec![
+                TxOutcome::Accepted,
+                TxOutcome::Accepted,
+                mismatch.clone(),
+                mismatch.clone(),
+                mismatch,
+                TxOutcome::Accepted,
+            ]
+        );
+
+        let expected = vec![
+            AccountReport::new(1, amount("0.0"), amount("4.0"), amount("4.0"), false),
+            AccountReport::new(2, amount("3.0"), amount("0.0"), amount("3.0"), false),
+        ];
+
+        assert_eq!(bank.get_accounts_report(), expected);
+    }
+
+    fn handle_input_file(bank: &mut Bank, file_name: &str) -> Vec<TxOutcome> {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push(file_name);
+
+        let actual_transactions =
+            transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
+        bank.handle_transactions(actual_transactions)
+    }
+
+    #[test]
+    fn test_withdrawals_cannot_be_disputed_with_the_not_disputable_policy() {
+        for file_name in [
+            "deposit_withdrawal_disputed.csv",
+            "deposit_withdrawal_resolved.csv",
+            "deposit_withdrawal_chargeback.csv",
+        ] {
+            let mut bank = Bank::new().with_dispute_policy(DisputePolicy::NotDisputable);
+            let outcomes = handle_input_file(&mut bank, file_name);
+
+            assert_eq!(
+                outcomes[2..],
+                vec![TxOutcome::Rejected(TxError::NotDisputable); outcomes.len() - 2]
+            );
+            let expected = vec![AccountReport::new(
+                1,
+                amount("6.0"),
+                amount("0.0"),
+                amount("6.0"),
+                false,
+            )];
+            assert_eq!(bank.get_accounts_report(), expected, "{file_name}");
+        }
+    }
+
+    #[test]
+    fn test_disputed_withdrawals_are_credited_back_with_the_credit_back_policy() {
+        for (file_name, expected) in [
+            (
+                "deposit_withdrawal_disputed.csv",
+                AccountReport::new(1, amount("10.0"), amount("-4.0"), amount("6.0"), false),
+            ),
+            (
+                "deposit_withdrawal_resolved.csv",
+                AccountReport::new(1, amount("6.0"), amount("0.0"), amount("6.0"), false),
+            ),
+            (
+                "deposit_withdrawal_chargeback.csv",
+                AccountReport::new(1, amount("10.0"), amount("0.0"), amount("10.0"), true),
+            ),
+        ] {
+            let mut bank = Bank::new().with_dispute_policy(DisputePolicy::CreditBack);
+            let outcomes = handle_input_file(&mut bank, file_name);
+
+            assert!(outcomes
+                .iter()
+                .all(|outcome| *outcome == TxOutcome::Accepted));
+            assert_eq!(bank.get_accounts_report(), vec![expected], "{file_name}");
+        }
+    }
+
+    #[test]
+    fn test_disputed_withdrawals_reserve_held_funds_with_the_reserve_held_policy() {
+        for (file_name, expected) in [
+            (
+                "deposit_withdrawal_disputed.csv",
+                AccountReport::new(1, amount("2.0"), amount("4.0"), amount("6.0"), false),
+            ),
+            (
+                "deposit_withdrawal_resolved.csv",
+                AccountReport::new(1, amount("6.0"), amount("0.0"), amount("6.0"), false),
+            ),
+            (
+                "deposit_withdrawal_chargeback.csv",
+                AccountReport::new(1, amount("10.0"), amount("0.0"), amount("10.0"), true),
+            ),
+        ] {
+            let mut bank = Bank::new().with_dispute_policy(DisputePolicy::ReserveHeld);
+            let outcomes = handle_input_file(&mut bank, file_name);
+
+            assert!(outcomes
+                .iter()
+                .all(|outcome| *outcome == TxOutcome::Accepted));
+            assert_eq!(bank.get_accounts_report(), vec![expected], "{file_name}");
+        }
+    }
+
+    #[test]
+    fn test_a_restored_snapshot_is_processed_incrementally() {
+        let state_dir = tempfile::tempdir().expect("Creating temporary directory failed!");
+        let state_file = state_dir.path().join("bank.state");
+
+        let mut bank = Bank::new();
+        handle_input_file(&mut bank, "deposit_disputed.csv");
+        bank.save_snapshot_file(&state_file)
+            .expect("Saving snapshot failed!");
+
+        let mut bank = Bank::load_snapshot_file(&state_file).expect("Loading snapshot failed!");
+        let outcomes = handle_input_file(&mut bank, "chargeback_next_day.csv");
+
+        let mut expected_bank = Bank::new();
+        handle_input_file(&mut expected_bank, "deposit_chargeback.csv");
+
+        assert_eq!(outcomes, vec![TxOutcome::Accepted]);
+        assert_eq!(
+            bank.get_accounts_report(),
+            expected_bank.get_accounts_report()
+        );
+    }
+
+    #[test]
+    fn test_processing_resumes_from_the_journal_after_a_crash() {
+        let journal_dir = tempfile::tempdir().expect("Creating temporary directory failed!");
+        let journal_file = journal_dir.path().join("bank.journal");
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("multiple_client_transactions.csv");
+        let all_transactions =
+            transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
+
+        let mut bank = Bank::new();
+        bank.recover_journal(&journal_file, 1)
+            .expect("Creating journal failed!");
+        bank.handle_transactions(all_transactions.iter().take(5).cloned());
+        // the process dies without writing a snapshot
+        drop(bank);
+
+        let mut bank = Bank::new();
+        let resume_position = bank
+            .recover_journal(&journal_file, 1)
+            .expect("Recovering journal failed!");
+        bank.handle_transactions(
+            all_transactions
+                .iter()
+                .skip(resume_position as usize)
+                .cloned(),
+        );
+
+        let mut expected_bank = Bank::new();
+        expected_bank.handle_transactions(all_transactions);
+
+        assert!(resume_position <= 5);
+        assert_eq!(
+            bank.get_accounts_report(),
+            expected_bank.get_accounts_report()
+        );
+    }
+
+    #[test]
+    fn test_file_stores_give_the_same_report_as_memory() {
+        let store_dir = tempfile::tempdir().expect("Creating temporary directory failed!");
+
+        for file_name in [
+            "multiple_client_transactions.csv",
+            "deposit_illegal_dispute_transitions.csv",
+            "cross_client_dispute.csv",
+            "deposit_withdrawal_chargeback.csv",
+        ] {
+            let mut bank = Bank::with_stores(
+                FileAccountStore::create(&store_dir.path().join("accounts"))
+                    .expect("Creating account store failed!"),
+                FileTxStore::create(&store_dir.path().join("txs"))
+                    .expect("Creating transaction store failed!"),
+            );
+            let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            csv_file.push("input_data");
+            csv_file.push(file_name);
+            let outcomes = bank.handle_transactions(
+                transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!"),
+            );
+
+            let mut expected_bank = Bank::new();
+            let expected_outcomes = handle_input_file(&mut expected_bank, file_name);
+
+            assert_eq!(outcomes, expected_outcomes, "{file_name}");
+            assert_eq!(
+                bank.try_get_accounts_report()
+                    .expect("Reading accounts failed!"),
+                expected_bank.get_accounts_report(),
+                "{file_name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sharded_processing_gives_the_same_outcomes_and_report() {
+        for file_name in [
+            "multiple_client_transactions.csv",
+            "cross_client_dispute.csv",
+            "transactions_after_chargeback.csv",
+            "deposit_illegal_dispute_transitions.csv",
+        ] {
+            let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            csv_file.push("input_data");
+            csv_file.push(file_name);
+            let all_transactions =
+                transactions::parse_transactions(&csv_file).expect("Parsing transactions failed!");
+
+            let mut bank = ShardedBank::new(3);
+            let outcomes = bank.handle_transactions(all_transactions);
+
+            let mut expected_bank = Bank::new();
+            let expected_outcomes = handle_input_file(&mut expected_bank, file_name);
+
+            assert_eq!(outcomes, expected_outcomes, "{file_name}");
+            assert_eq!(
+                bank.get_accounts_report(),
+                expected_bank.get_accounts_report(),
+                "{file_name}"
+            );
+        }
+    }
 }
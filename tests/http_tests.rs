@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+    use tx_engine::{bank::Bank, server::http};
+
+    async fn request(
+        router: &Router,
+        method: Method,
+        uri: &str,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_owned()))
+            .expect("Valid request");
+        let response = router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("Request failed!");
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("Reading body failed!")
+            .to_bytes();
+        (
+            status,
+            String::from_utf8(body.to_vec()).expect("UTF-8 body"),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_submitted_transactions_are_applied() {
+        let router = http::router(Arc::new(Mutex::new(Bank::new())));
+
+        assert_eq!(
+            request(
+                &router,
+                Method::POST,
+                "/transactions",
+                r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}"#
+            )
+            .await,
+            (StatusCode::OK, r#"{"outcome":"accepted"}"#.to_owned())
+        );
+        assert_eq!(
+            request(
+                &router,
+                Method::POST,
+                "/transactions",
+                r#"{"type": "withdrawal", "client": 1, "tx": 2, "amount": 3.0001}"#
+            )
+            .await,
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                r#"{"outcome":"rejected","reason":"Insufficient available funds"}"#.to_owned()
+            )
+        );
+        assert_eq!(
+            request(
+                &router,
+                Method::POST,
+                "/transactions",
+                r#"{"type": "dispute", "client": 1, "tx": 1, "amount": "1.0"}"#
+            )
+            .await,
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"outcome":"invalid","reason":"Dispute transactions cannot contain an amount"}"#
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            request(
+                &router,
+                Method::POST,
+                "/transactions",
+                r#"{"type": "dispute", "client": 1, "tx": 1}"#
+            )
+            .await
+            .0,
+            StatusCode::OK
+        );
+
+        assert_eq!(
+            request(&router, Method::GET, "/accounts/1", "").await,
+            (
+                StatusCode::OK,
+                r#"{"client":1,"available":"0.0000","held":"2.5000","total":"2.5000","locked":false}"#
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            request(&router, Method::GET, "/accounts", "").await,
+            (
+                StatusCode::OK,
+                r#"[{"client":1,"available":"0.0000","held":"2.5000","total":"2.5000","locked":false}]"#
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            request(&router, Method::GET, "/transactions/1", "").await,
+            (
+                StatusCode::OK,
+                r#"{"tx":1,"client":1,"type":"deposit","amount":"2.5000","state":"Disputed"}"#
+                    .to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_accounts_and_transactions_are_not_found() {
+        let router = http::router(Arc::new(Mutex::new(Bank::new())));
+
+        assert_eq!(
+            request(&router, Method::GET, "/accounts/7", "").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            request(&router, Method::GET, "/transactions/7", "").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            request(&router, Method::POST, "/transactions", "deposit,1,1,1.0")
+                .await
+                .1,
+            r#"{"outcome":"invalid","reason":"record is not valid JSON"}"#
+        );
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+    use tx_engine::{bank::Bank, server};
+
+    async fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Binding loopback failed!");
+        let address = listener
+            .local_addr()
+            .expect("Listener has an address")
+            .to_string();
+        tokio::spawn(server::serve(listener, Arc::new(Mutex::new(Bank::new()))));
+        address
+    }
+
+    /// Send all lines and read one response line for each of them
+    async fn send_lines(address: &str, lines: &[&str]) -> Vec<String> {
+        let stream = TcpStream::connect(address)
+            .await
+            .expect("Connecting failed!");
+        let (reader, mut writer) = stream.into_split();
+        let mut responses = BufReader::new(reader).lines();
+        let mut result = Vec::new();
+        for line in lines {
+            writer
+                .write_all(format!("{line}\n").as_bytes())
+                .await
+                .expect("Sending failed!");
+            let response = responses
+                .next_line()
+                .await
+                .expect("Receiving failed!")
+                .expect("Response expected");
+            result.push(response);
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn test_transactions_of_concurrent_connections_are_applied() {
+        let address = start_server().await;
+
+        let connections = (1..=3).map(|client| {
+            let address = address.clone();
+            tokio::spawn(async move {
+                let deposit = format!("deposit, {client}, {client}, 2.0");
+                let withdrawal = format!("withdrawal, {client}, {}, 5.0", client + 10);
+                send_lines(&address, &[&deposit, &withdrawal]).await
+            })
+        });
+        for connection in connections {
+            assert_eq!(
+                connection.await.expect("Connection failed!"),
+                vec!["accepted", "rejected,Insufficient available funds"]
+            );
+        }
+
+        let stream = TcpStream::connect(&address)
+            .await
+            .expect("Connecting failed!");
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(b"type, client, tx, amount\ndeposit, 1\nreport\n")
+            .await
+            .expect("Sending failed!");
+        let mut lines = BufReader::new(reader).lines();
+        let mut responses = Vec::new();
+        while let Some(line) = lines.next_line().await.expect("Receiving failed!") {
+            if line.is_empty() {
+                break;
+            }
+            responses.push(line);
+        }
+
+        assert_eq!(
+            responses,
+            vec![
+                "invalid,record cannot be parsed",
+                "client,available,held,total,locked",
+                "1,2.0000,0.0000,2.0000,false",
+                "2,2.0000,0.0000,2.0000,false",
+                "3,2.0000,0.0000,2.0000,false",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_lines_are_answered_without_being_buffered() {
+        let address = start_server().await;
+        let oversized = "9".repeat(10 * server::MAX_LINE_LEN);
+
+        assert_eq!(
+            send_lines(
+                &address,
+                &[&format!("deposit, 1, 1, {oversized}"), "deposit, 1, 2, 2.0",]
+            )
+            .await,
+            vec![
+                format!("invalid,line is longer than {} bytes", server::MAX_LINE_LEN),
+                "accepted".to_owned(),
+            ]
+        );
+    }
+}
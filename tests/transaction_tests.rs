@@ -1,7 +1,16 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
-    use tx_engine::transactions::{Kind, Transaction};
+    use crate::common::amount;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use tx_engine::amount::Amount;
+    use tx_engine::transactions;
+    use tx_engine::transactions::{
+        InputFormat, Kind, ParseMode, ParseTxError, RowError, Transaction, TransactionReader,
+    };
 
     #[test]
     fn test_basic_transactions_are_correctly_read_from_csv() {
@@ -10,11 +19,11 @@ fn test_basic_transactions_are_correctly_read_from_csv() {
         csv_file.push("basic_transactions.csv");
 
         let expected_transactions = vec![
-            Transaction::new(Kind::Deposit, 1, 1, Some(1.0)),
-            Transaction::new(Kind::Deposit, 2, 2, Some(2.0)),
-            Transaction::new(Kind::Deposit, 1, 3, Some(2.0)),
-            Transaction::new(Kind::Withdrawal, 1, 4, Some(1.5)),
-            Transaction::new(Kind::Withdrawal, 2, 5, Some(3.0)),
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))),
+            Transaction::new(Kind::Deposit, 2, 2, Some(amount("2.0"))),
+            Transaction::new(Kind::Deposit, 1, 3, Some(amount("2.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 4, Some(amount("1.5"))),
+            Transaction::new(Kind::Withdrawal, 2, 5, Some(amount("3.0"))),
         ];
 
         let actual_transactions = tx_engine::transactions::parse_transactions(&csv_file)
@@ -29,8 +38,8 @@ fn test_transactions_with_optional_field_are_correctly_read_from_csv() {
         csv_file.push("all_transactions_types.csv");
 
         let expected_transactions = vec![
-            Transaction::new(Kind::Deposit, 1, 1, Some(1.0)),
-            Transaction::new(Kind::Withdrawal, 1, 2, Some(3.0)),
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 2, Some(amount("3.0"))),
             Transaction::new(Kind::Dispute, 1, 2, None),
             Transaction::new(Kind::Resolve, 1, 2, None),
             Transaction::new(Kind::Chargeback, 1, 2, None),
@@ -81,4 +90,364 @@ fn test_abundant_amount_in_dispute_transaction() {
             )
         );
     }
+
+    #[test]
+    fn test_deposits_and_withdrawals_must_contain_a_positive_amount() {
+        for (line, reason) in [
+            (
+                "deposit, 1, 1, -3.0",
+                "Deposit transactions must contain a positive amount",
+            ),
+            (
+                "deposit, 1, 1, 0.0",
+                "Deposit transactions must contain a positive amount",
+            ),
+            (
+                "withdrawal, 1, 1, -50",
+                "Withdrawal transactions must contain a positive amount",
+            ),
+            (
+                "withdrawal, 1, 1, 0",
+                "Withdrawal transactions must contain a positive amount",
+            ),
+        ] {
+            assert_eq!(
+                *transactions::parse_csv_line(line)
+                    .unwrap_err()
+                    .current_context(),
+                ParseTxError::InvalidInput(reason.to_owned()),
+                "{line}"
+            );
+        }
+        assert_eq!(
+            *transactions::parse_json_transaction(
+                r#"{"type": "deposit", "client": 1, "tx": 1, "amount": -3.0}"#
+            )
+            .unwrap_err()
+            .current_context(),
+            ParseTxError::InvalidInput(
+                "Deposit transactions must contain a positive amount".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn test_amounts_are_validated_per_kind() {
+        for (line, expected) in [
+            ("fee, 1, 1, 0.5", Ok(Some(amount("0.5")))),
+            ("interest, 1, 1, 0.1", Ok(Some(amount("0.1")))),
+            ("adjustment, 1, 1, -2.0", Ok(Some(amount("-2.0")))),
+            ("fee, 1, 1,", Err("Fee transactions must contain an amount")),
+            (
+                "fee, 1, 1, -0.5",
+                Err("Fee transactions must contain a positive amount"),
+            ),
+            (
+                "fee, 1, 1, 0.0",
+                Err("Fee transactions must contain a positive amount"),
+            ),
+            (
+                "interest, 1, 1, -0.1",
+                Err("Interest transactions must contain a positive amount"),
+            ),
+            (
+                "interest, 1, 1, 0.0",
+                Err("Interest transactions must contain a positive amount"),
+            ),
+            (
+                "adjustment, 1, 1,",
+                Err("Adjustment transactions must contain an amount"),
+            ),
+            (
+                "adjustment, 1, 1, 0.0",
+                Err("Adjustment transactions cannot contain a zero amount"),
+            ),
+        ] {
+            let result = transactions::parse_csv_line(line);
+            match expected {
+                Ok(expected) => {
+                    assert_eq!(result.expect(line).amount, expected, "{line}")
+                }
+                Err(reason) => assert_eq!(
+                    *result.unwrap_err().current_context(),
+                    ParseTxError::InvalidInput(reason.to_owned()),
+                    "{line}"
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_transactions_are_streamed_one_record_at_a_time() {
+        let csv = "type, client, tx, amount\ndeposit, 1, 1, 1.0\nwithdrawal, 1, 2, 0.5\n";
+        let mut reader = TransactionReader::from_reader(csv.as_bytes())
+            .expect("Building the transaction reader failed");
+
+        assert_eq!(
+            reader.next().expect("First record").expect("Valid record"),
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("1.0")))
+        );
+        assert_eq!(reader.line(), 2);
+        assert_eq!(
+            reader.next().expect("Second record").expect("Valid record"),
+            Transaction::new(Kind::Withdrawal, 1, 2, Some(amount("0.5")))
+        );
+        assert_eq!(reader.line(), 3);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_streaming_continues_after_an_invalid_record() {
+        let csv = "type, client, tx, amount\ndeposit, 1, 1\ndeposit, 1, 2, 2.0\n";
+        let mut reader = TransactionReader::from_reader(csv.as_bytes())
+            .expect("Building the transaction reader failed");
+
+        assert!(reader.next().expect("First record").is_err());
+        assert_eq!(reader.line(), 2);
+        assert_eq!(
+            reader.next().expect("Second record").expect("Valid record"),
+            Transaction::new(Kind::Deposit, 1, 2, Some(amount("2.0")))
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_returns_the_first_invalid_record() {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("partially_invalid_transactions.csv");
+
+        assert_eq!(
+            *tx_engine::transactions::parse_transactions(&csv_file)
+                .unwrap_err()
+                .current_context(),
+            ParseTxError::InvalidInput("Deposit transactions must contain an amount".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_lenient_mode_skips_invalid_records_and_collects_their_line() {
+        let mut csv_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        csv_file.push("input_data");
+        csv_file.push("partially_invalid_transactions.csv");
+
+        let mut reader = TransactionReader::from_path(&csv_file)
+            .expect("Building the transaction reader failed")
+            .with_mode(ParseMode::Lenient);
+        let actual_transactions = reader
+            .by_ref()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Parsing transactions failed");
+
+        let expected_transactions = vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+            Transaction::new(Kind::Withdrawal, 1, 3, Some(amount("2.0"))),
+            Transaction::new(Kind::Deposit, 2, 5, Some(amount("1.0"))),
+        ];
+        let expected_row_errors = vec![
+            RowError {
+                line: 3,
+                error: ParseTxError::InvalidInput(
+                    "Deposit transactions must contain an amount".to_owned(),
+                ),
+            },
+            RowError {
+                line: 5,
+                error: ParseTxError::InvalidInput("record cannot be parsed".to_owned()),
+            },
+            RowError {
+                line: 6,
+                error: ParseTxError::InvalidInput(
+                    "Dispute transactions cannot contain an amount".to_owned(),
+                ),
+            },
+        ];
+
+        assert_eq!(actual_transactions, expected_transactions);
+        assert_eq!(reader.row_errors(), expected_row_errors);
+    }
+
+    #[test]
+    fn test_single_csv_lines_are_parsed_without_header() {
+        assert_eq!(
+            transactions::parse_csv_line(" deposit, 1, 2, 1.5 ").expect("Parsing line failed!"),
+            Transaction::new(Kind::Deposit, 1, 2, Some(amount("1.5")))
+        );
+        assert_eq!(
+            transactions::parse_csv_line("dispute,1,2").expect("Parsing line failed!"),
+            Transaction::new(Kind::Dispute, 1, 2, None)
+        );
+        assert_eq!(
+            *transactions::parse_csv_line("dispute, 1, 2, 1.5")
+                .unwrap_err()
+                .current_context(),
+            ParseTxError::InvalidInput("Dispute transactions cannot contain an amount".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_json_amounts_are_read_exactly_from_numbers_and_strings() {
+        assert_eq!(
+            transactions::parse_json_transaction(
+                r#"{"type": "deposit", "client": 1, "tx": 2, "amount": 922337203685477.5807}"#
+            )
+            .expect("Parsing JSON failed!"),
+            Transaction::new(Kind::Deposit, 1, 2, Some(Amount::MAX))
+        );
+        assert_eq!(
+            transactions::parse_json_transaction(
+                r#"{"type": "withdrawal", "client": 1, "tx": 3, "amount": "0.00005"}"#
+            )
+            .expect("Parsing JSON failed!"),
+            Transaction::new(Kind::Withdrawal, 1, 3, Some(amount("0.0001")))
+        );
+        assert_eq!(
+            transactions::parse_json_transaction(r#"{"type": "resolve", "client": 1, "tx": 2}"#)
+                .expect("Parsing JSON failed!"),
+            Transaction::new(Kind::Resolve, 1, 2, None)
+        );
+    }
+
+    #[test]
+    fn test_json_and_json_lines_give_the_same_transactions_as_csv() {
+        let mut input_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        input_dir.push("input_data");
+
+        let expected_transactions =
+            transactions::parse_transactions(&input_dir.join("basic_transactions.csv"))
+                .expect("Parsing CSV failed");
+        for file_name in ["basic_transactions.json", "basic_transactions.jsonl"] {
+            assert_eq!(
+                transactions::parse_transactions(&input_dir.join(file_name))
+                    .expect("Parsing JSON failed"),
+                expected_transactions,
+                "{file_name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_lines_are_skipped_with_their_line() {
+        let mut jsonl_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        jsonl_file.push("input_data");
+        jsonl_file.push("partially_invalid_transactions.jsonl");
+
+        let mut reader = TransactionReader::from_path(&jsonl_file)
+            .expect("Building the transaction reader failed")
+            .with_mode(ParseMode::Lenient);
+        let actual_transactions = reader
+            .by_ref()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Parsing transactions failed");
+
+        assert_eq!(
+            actual_transactions,
+            vec![
+                Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+                Transaction::new(Kind::Withdrawal, 1, 3, Some(amount("2.0"))),
+                Transaction::new(Kind::Deposit, 2, 6, Some(amount("1.0"))),
+            ]
+        );
+        assert_eq!(
+            reader
+                .row_errors()
+                .iter()
+                .map(|row_error| row_error.line)
+                .collect::<Vec<_>>(),
+            vec![2, 4, 6, 7]
+        );
+        assert_eq!(
+            reader.row_errors()[3].error,
+            ParseTxError::InvalidInput("record is not valid JSON".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_invalid_json_ends_a_json_array() {
+        let mut json_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        json_file.push("input_data");
+        json_file.push("partially_invalid_transactions.json");
+
+        let mut reader = TransactionReader::from_path(&json_file)
+            .expect("Building the transaction reader failed")
+            .with_mode(ParseMode::Lenient);
+        let actual_transactions = reader
+            .by_ref()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Parsing transactions failed");
+
+        assert_eq!(
+            actual_transactions,
+            vec![
+                Transaction::new(Kind::Deposit, 1, 1, Some(amount("5.0"))),
+                Transaction::new(Kind::Withdrawal, 1, 3, Some(amount("2.0"))),
+            ]
+        );
+        assert_eq!(
+            reader.row_errors(),
+            &[
+                RowError {
+                    line: 3,
+                    error: ParseTxError::InvalidInput(
+                        "Deposit transactions must contain an amount".to_owned(),
+                    ),
+                },
+                RowError {
+                    line: 5,
+                    error: ParseTxError::InvalidInput("input is not a valid JSON array".to_owned()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_input_format_follows_the_file_extension() {
+        assert_eq!(
+            InputFormat::from_path(Path::new("feed.jsonl")),
+            InputFormat::JsonLines
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("feed.json")),
+            InputFormat::Json
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("feed.txt")),
+            InputFormat::Csv
+        );
+        assert_eq!(
+            InputFormat::from_path(Path::new("feed.jsonl.gz")),
+            InputFormat::JsonLines
+        );
+        assert_eq!(InputFormat::from_path(Path::new("-")), InputFormat::Csv);
+        assert_eq!("jsonl".parse(), Ok(InputFormat::JsonLines));
+    }
+
+    #[test]
+    fn test_gzip_compressed_input_is_decompressed() {
+        let mut input_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        input_dir.push("input_data");
+        let expected_transactions =
+            transactions::parse_transactions(&input_dir.join("basic_transactions.jsonl"))
+                .expect("Parsing JSON Lines failed");
+
+        let dir = tempfile::tempdir().expect("Creating a temporary directory failed");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(
+                &std::fs::read(input_dir.join("basic_transactions.jsonl"))
+                    .expect("Reading file failed"),
+            )
+            .expect("Compressing failed");
+        let compressed = encoder.finish().expect("Compressing failed");
+
+        // compression is recognized by the extension as well as by the content
+        for file_name in ["basic_transactions.jsonl.gz", "basic_transactions.jsonl"] {
+            let path = dir.path().join(file_name);
+            std::fs::write(&path, &compressed).expect("Writing file failed");
+            assert_eq!(
+                transactions::parse_transactions(&path).expect("Parsing gzip file failed"),
+                expected_transactions,
+                "{file_name}"
+            );
+        }
+    }
 }